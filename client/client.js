@@ -158,6 +158,64 @@ let rematch = () => {
 
 }
 
+let hint = () => {
+   fetch('./hint/'.concat(credentials), { method: 'GET' })
+   .then((response) => {
+      if (response.status === 200) {
+         response.json().then((hint) => {
+            notifications.innerText = "Try " + hint.name + " (" + hint.remaining + " hints left)";
+         });
+      } else if (response.status === 429) {
+         notifications.innerText = "No hints left in this game!";
+      } else if (response.status === 403) {
+         notifications.innerText = "Hints are turned off in this game.";
+      } else if (response.status === 409) {
+         notifications.innerText = "Hints are for your own turn.";
+      }
+   });
+}
+
+let sendchat = (event) => {
+   event.preventDefault();
+   let text = chatfield.value;
+   if (!text) {
+      return;
+   }
+   fetch('./chat/'.concat(credentials), { method: 'POST', body: text })
+   .then((response) => {
+      if (response.status === 200) {
+         chatfield.value = "";
+      } else if (response.status === 429) {
+         notifications.innerText = "Slow down, you are chatting too fast!";
+      }
+   });
+}
+
+let react = (reaction) => {
+   fetch('./react/'.concat(reaction, '/', credentials), { method: 'POST' })
+   .then((response) => {
+      console.log(response.status);
+   });
+}
+
+let showreaction = (reaction) => {
+   let bubble = document.createElement("span");
+   bubble.className = "floating-reaction";
+   bubble.innerText = reaction.emoji;
+   bubble.title = reaction.name;
+   bubble.style.left = (10 + Math.random() * 80) + "%";
+   bubble.addEventListener("animationend", () => bubble.remove());
+   floating.append(bubble);
+}
+
+let showchat = (msg) => {
+   let item = document.createElement("li");
+   item.className = msg.channel;
+   item.innerText = (msg.avatar ? msg.avatar + " " : "") + msg.name + ": " + msg.text;
+   messages.append(item);
+   messages.scrollTop = messages.scrollHeight;
+}
+
 let updatefields = (gamestate) => {
    fields.forEach((field, i) => {
       //console.log(field.getAttribute("state"), " ==? ", data[i]);
@@ -210,6 +268,19 @@ let showvictory = (condition) => {
    }
 }
 
+let showplayers = (players) => {
+   if (!players) {
+      return;
+   }
+   playersdiv.innerHTML = "";
+   ["X", "O"].forEach((mark, i) => {
+      let span = document.createElement("span");
+      let player = players[i];
+      span.innerText = mark + ": " + (player ? (player.avatar ? player.avatar + " " : "") + player.name : "...");
+      playersdiv.append(span);
+   });
+}
+
 let copy_url = async (sharedata) => {
    var copyText = document.getElementById("urlfield");
    copyText.select();
@@ -243,6 +314,19 @@ let board = document.getElementById("board");
 let overlay = document.querySelector(".overlay");
 board.style.display = "none";
 let urldiv = document.getElementById("url");
+let playersdiv = document.getElementById("players");
+let profilediv = document.getElementById("profile");
+let namefield = document.getElementById("namefield");
+let avatarfield = document.getElementById("avatarfield");
+namefield.value = localStorage.getItem("name") || "";
+avatarfield.value = localStorage.getItem("avatar") || "";
+let passphrasefield = document.getElementById("passphrasefield");
+passphrasefield.value = sessionStorage.getItem("passphrase") || "";
+let chatdiv = document.getElementById("chat");
+let messages = document.getElementById("messages");
+let chatfield = document.getElementById("chatfield");
+let floating = document.getElementById("floating");
+document.getElementById("chatform").addEventListener("submit", sendchat);
 let fields = [];
 let credentials = "";
 let sharedata = {
@@ -254,39 +338,78 @@ urldiv.innerHTML = '<input id="urlfield" type="url" value="' + window.location.h
 
 //init_board();
 
-let events = new EventSource("./events");
-events.onmessage = (event) => {
-    let data = JSON.parse(event.data);
-    let gamestate = data.gamestate;
-    let outcome = data.outcome;
-
-    console.log(data);
-    updatefields(gamestate);
-    if (outcome) {
-      showvictory(outcome[1]);
-      console.log("Outcome:: ",  outcome[0]);
-      if (outcome[0] === 'Empty') {
-         notifications.innerHTML = "It's a draw! " + '<button onclick="rematch()">Rematch!</button>';
-      } else {
-         notifications.innerHTML = "Player " + outcome[0] +" wins! " + '<button onclick="rematch()">Rematch!</button>';
+let connect = () => {
+   localStorage.setItem("name", namefield.value);
+   localStorage.setItem("avatar", avatarfield.value);
+   profilediv.style.display = "none";
+   sessionStorage.setItem("passphrase", passphrasefield.value);
+   let query = new URLSearchParams({ name: namefield.value, avatar: avatarfield.value });
+   if (passphrasefield.value) {
+      query.append("passphrase", passphrasefield.value);
+   }
+   let events = new EventSource("./events?" + query.toString());
+   events.onerror = () => {
+      // the server refuses connections without the right passphrase
+      if (!credentials) {
+         events.close();
+         notifications.innerText = "Could not join, check the passphrase!";
+         profilediv.style.display = "flex";
       }
-    }
+   };
+   listen(events);
+}
+
+// protected games want the passphrase for their record as well
+let recordlink = () => {
+   let passphrase = sessionStorage.getItem("passphrase");
+   return passphrase ? "./record?" + new URLSearchParams({ passphrase: passphrase }).toString() : "./record";
 }
-events.addEventListener("notification", (event) => {
-   notifications.innerText = event.data;
-   console.log("Notification: ",event.data);
-})
 
-events.addEventListener("credentials", (event) => {
-   credentials = event.data;
-   console.log("Credentials: ",event.data);
-})
+// every event carries a versioned message, see src/protocol.rs
+let message = (event) => JSON.parse(event.data).data;
+
+let listen = (events) => {
+   events.addEventListener("state", (event) => {
+       let data = message(event);
+       let gamestate = data.gamestate;
+       let outcome = data.outcome;
 
-events.addEventListener("startgame", (event) => {  
-   console.log("Start Game!");
-   urldiv.style.display = "none";
-   document.getElementById("urllabel").style.display = "none";
-   init_board();
-})
-        
+       console.log(data);
+       showplayers(data.players);
+       updatefields(gamestate);
+       if (outcome) {
+         showvictory(outcome[1]);
+         console.log("Outcome:: ",  outcome[0]);
+         if (outcome[0] === 'Empty') {
+            notifications.innerHTML = "It's a draw! " + '<button onclick="rematch()">Rematch!</button> <a href="' + recordlink() + '" download>Save record</a>';
+         } else {
+            notifications.innerHTML = "Player " + outcome[0] +" wins! " + '<button onclick="rematch()">Rematch!</button> <a href="' + recordlink() + '" download>Save record</a>';
+         }
+       }
+   })
+   events.addEventListener("notification", (event) => {
+      notifications.innerText = message(event);
+      console.log("Notification: ", message(event));
+   })
 
+   events.addEventListener("chat", (event) => {
+      showchat(message(event));
+   })
+
+   events.addEventListener("reaction", (event) => {
+      showreaction(message(event));
+   })
+
+   events.addEventListener("credentials", (event) => {
+      credentials = message(event);
+      chatdiv.style.display = "block";
+      console.log("Credentials: ", credentials);
+   })
+
+   events.addEventListener("startgame", (event) => {  
+      console.log("Start Game!");
+      urldiv.style.display = "none";
+      document.getElementById("urllabel").style.display = "none";
+      init_board();
+   })
+}
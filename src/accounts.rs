@@ -0,0 +1,211 @@
+/// Types and methods related to player accounts, stored in a local SQLite database
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const MIN_USERNAME_LEN: usize = 3;
+pub const MAX_USERNAME_LEN: usize = 20;
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+pub type UserId = i64;
+
+#[derive(Debug)]
+pub enum AccountError {
+    InvalidUsername,
+    WeakPassword,
+    UsernameTaken,
+    WrongCredentials,
+    Hashing,
+    Database(rusqlite::Error),
+}
+impl From<rusqlite::Error> for AccountError {
+    fn from(e: rusqlite::Error) -> Self {
+        AccountError::Database(e)
+    }
+}
+impl std::fmt::Display for AccountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidUsername => write!(
+                f,
+                "Usernames are {MIN_USERNAME_LEN} to {MAX_USERNAME_LEN} letters, digits, - or _"
+            ),
+            Self::WeakPassword => write!(f, "Passwords need at least {MIN_PASSWORD_LEN} characters"),
+            Self::UsernameTaken => write!(f, "This username is taken"),
+            Self::WrongCredentials => write!(f, "Wrong username or password"),
+            Self::Hashing => write!(f, "Could not hash password"),
+            Self::Database(e) => write!(f, "Database error: {e}"),
+        }
+    }
+}
+impl std::error::Error for AccountError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct User {
+    pub id: UserId,
+    pub username: String,
+}
+
+/// Registered players, the connection is shared behind a mutex
+pub struct Accounts {
+    conn: Mutex<Connection>,
+}
+impl Accounts {
+    /// Open (or create) the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AccountError> {
+        Accounts::init(Connection::open(path)?)
+    }
+    /// A database that only lives as long as the value, for tests and throwaway servers
+    pub fn in_memory() -> Result<Self, AccountError> {
+        Accounts::init(Connection::open_in_memory()?)
+    }
+    fn init(conn: Connection) -> Result<Self, AccountError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                password_hash TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            );
+            CREATE TABLE IF NOT EXISTS ratings (
+                user_id INTEGER PRIMARY KEY REFERENCES users(id),
+                rating REAL NOT NULL,
+                wins INTEGER NOT NULL DEFAULT 0,
+                losses INTEGER NOT NULL DEFAULT 0,
+                draws INTEGER NOT NULL DEFAULT 0
+            );",
+        )?;
+        Ok(Accounts {
+            conn: Mutex::new(conn),
+        })
+    }
+    pub(crate) fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // a panic while holding the connection leaves nothing half-written behind
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Create an account, the password is stored as an argon2 hash
+    pub fn register(&self, username: &str, password: &str) -> Result<User, AccountError> {
+        let username = username.trim();
+        if !valid_username(username) {
+            return Err(AccountError::InvalidUsername);
+        }
+        if password.chars().count() < MIN_PASSWORD_LEN {
+            return Err(AccountError::WeakPassword);
+        }
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|_| AccountError::Hashing)?
+            .to_string();
+        let conn = self.conn();
+        match conn.execute(
+            "INSERT INTO users (username, password_hash) VALUES (?1, ?2)",
+            params![username, hash],
+        ) {
+            Ok(_) => Ok(User {
+                id: conn.last_insert_rowid(),
+                username: username.to_string(),
+            }),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                Err(AccountError::UsernameTaken)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+    /// Check a username and password, returns the user if they match
+    pub fn login(&self, username: &str, password: &str) -> Result<User, AccountError> {
+        let row: Option<(UserId, String, String)> = self
+            .conn()
+            .query_row(
+                "SELECT id, username, password_hash FROM users WHERE username = ?1",
+                params![username.trim()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let (id, username, hash) = row.ok_or(AccountError::WrongCredentials)?;
+        let hash = PasswordHash::new(&hash).map_err(|_| AccountError::Hashing)?;
+        match Argon2::default().verify_password(password.as_bytes(), &hash) {
+            Ok(()) => Ok(User { id, username }),
+            Err(_) => Err(AccountError::WrongCredentials),
+        }
+    }
+    pub fn get(&self, id: UserId) -> Result<Option<User>, AccountError> {
+        Ok(self
+            .conn()
+            .query_row(
+                "SELECT id, username FROM users WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(User {
+                        id: row.get(0)?,
+                        username: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+}
+
+fn valid_username(username: &str) -> bool {
+    (MIN_USERNAME_LEN..=MAX_USERNAME_LEN).contains(&username.chars().count())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_and_login() {
+        let accounts = Accounts::in_memory().unwrap();
+        let alice = accounts.register("alice", "correct horse").unwrap();
+        assert_eq!(alice, accounts.login("alice", "correct horse").unwrap());
+        assert_eq!(alice, accounts.login("ALICE", "correct horse").unwrap());
+        assert_eq!(Some(alice.clone()), accounts.get(alice.id).unwrap());
+        assert!(matches!(
+            accounts.login("alice", "battery staple"),
+            Err(AccountError::WrongCredentials)
+        ));
+        assert!(matches!(
+            accounts.login("bob", "correct horse"),
+            Err(AccountError::WrongCredentials)
+        ));
+    }
+
+    #[test]
+    fn registration_is_validated() {
+        let accounts = Accounts::in_memory().unwrap();
+        accounts.register("alice", "correct horse").unwrap();
+        assert!(matches!(
+            accounts.register("Alice", "another password"),
+            Err(AccountError::UsernameTaken)
+        ));
+        assert!(matches!(
+            accounts.register("<b>", "correct horse"),
+            Err(AccountError::InvalidUsername)
+        ));
+        assert!(matches!(
+            accounts.register("carol", "short"),
+            Err(AccountError::WeakPassword)
+        ));
+    }
+
+    #[test]
+    fn passwords_are_not_stored_in_plain_text() {
+        let accounts = Accounts::in_memory().unwrap();
+        accounts.register("alice", "correct horse").unwrap();
+        let hash: String = accounts
+            .conn()
+            .query_row("SELECT password_hash FROM users", [], |row| row.get(0))
+            .unwrap();
+        assert!(!hash.contains("correct horse"));
+        assert!(hash.starts_with("$argon2"));
+    }
+}
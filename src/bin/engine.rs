@@ -0,0 +1,45 @@
+/// One of the crate's bots as an engine speaking the engine protocol over stdin and stdout
+///
+/// ```text
+/// engine [--bot builtin:hardest] [--seed 42]
+/// ```
+///
+/// Bots are named as in `Bot::from_str`, the builtin bot at its hardest level by default.
+use multiplayer_tictactoe::bot::{engine, Bot};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut name = String::from("builtin");
+    let mut seed: u64 = rand::random();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--bot", Some(bot)) => name = bot,
+            ("--seed", Some(s)) => match s.parse() {
+                Ok(s) => seed = s,
+                Err(_) => return usage(&format!("Bad seed {s}")),
+            },
+            _ => return usage(&format!("Unknown argument {arg}")),
+        }
+    }
+    let bot = match name.parse::<Bot>() {
+        Ok(bot) => bot.with_seed(seed),
+        Err(_) => return usage(&format!("Unknown bot {name}")),
+    };
+    let stdin = std::io::stdin().lock();
+    let stdout = std::io::stdout().lock();
+    match engine::serve(&bot, &name, stdin, stdout, &mut StdRng::seed_from_u64(seed)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage(error: &str) -> ExitCode {
+    eprintln!("{error}\nusage: engine [--bot name] [--seed N]");
+    ExitCode::from(2)
+}
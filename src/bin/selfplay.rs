@@ -0,0 +1,140 @@
+/// Plays bots against each other without the web server, and writes statistics and game records
+///
+/// ```text
+/// selfplay [--engines builtin:easy,perfect,mcts:500] [--games 100] [--seed 42] [--out selfplay] [--expect-ordered]
+/// ```
+///
+/// Engines are named as in `Bot::from_str`. Every engine plays every other engine `--games`
+/// times with either side. With `--expect-ordered` the run fails unless every engine is
+/// stronger than the ones before it, which makes it a regression guard for engine changes.
+use multiplayer_tictactoe::bot::calibration::Calibration;
+use multiplayer_tictactoe::bot::Bot;
+use multiplayer_tictactoe::tictactoe::record::Record;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const DEFAULT_ENGINES: &str =
+    "builtin:easiest,builtin:easy,builtin:beatable,builtin:medium,builtin:hard,builtin:hardest,mcts:500,perfect";
+
+struct Options {
+    engines: Vec<String>,
+    games: usize,
+    seed: u64,
+    out: PathBuf,
+    expect_ordered: bool,
+}
+
+#[derive(Serialize)]
+struct Stats<'a> {
+    seed: u64,
+    games: usize,
+    strength: Vec<f64>,
+    #[serde(flatten)]
+    calibration: &'a Calibration,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{e}\nusage: selfplay [--engines a,b,...] [--games N] [--seed N] [--out DIR] [--expect-ordered]");
+            return ExitCode::from(2);
+        }
+    };
+    let mut bots = Vec::new();
+    for name in &options.engines {
+        match name.parse::<Bot>() {
+            // searches are seeded too, so the same seed plays the same games
+            Ok(bot) => bots.push((name.clone(), bot.with_seed(options.seed))),
+            Err(_) => {
+                eprintln!("Unknown engine {name}");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    match run(&options, &bots) {
+        Ok(calibration) => {
+            print!("{calibration}");
+            println!(
+                "seed {}, written to {}",
+                options.seed,
+                options.out.display()
+            );
+            if options.expect_ordered && !calibration.is_ordered() {
+                eprintln!("Engines are not ordered by strength");
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Could not write to {}: {e}", options.out.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        engines: DEFAULT_ENGINES.split(',').map(String::from).collect(),
+        games: 100,
+        seed: rand::random(),
+        out: PathBuf::from("selfplay"),
+        expect_ordered: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--engines" => options.engines = value()?.split(',').map(String::from).collect(),
+            "--games" => options.games = value()?.parse().map_err(|_| "Bad number of games")?,
+            "--seed" => options.seed = value()?.parse().map_err(|_| "Bad seed")?,
+            "--out" => options.out = PathBuf::from(value()?),
+            "--expect-ordered" => options.expect_ordered = true,
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+    if options.engines.len() < 2 {
+        return Err("At least two engines are needed".into());
+    }
+    Ok(options)
+}
+
+/// Play every pairing, writing the records of the games as they finish
+fn run(options: &Options, bots: &[(String, Bot)]) -> std::io::Result<Calibration> {
+    fs::create_dir_all(&options.out)?;
+    let mut records = BufWriter::new(File::create(options.out.join("games.pgn"))?);
+    let mut written = Ok(());
+    let mut round = 0;
+    let calibration = Calibration::run(bots, options.games, options.seed, |x, o, board| {
+        round += 1;
+        let headers = vec![
+            ("Event".into(), "Self-play".into()),
+            ("Round".into(), round.to_string()),
+            ("X".into(), bots[x].0.clone()),
+            ("O".into(), bots[o].0.clone()),
+            ("Seed".into(), options.seed.to_string()),
+        ];
+        if written.is_ok() {
+            written = writeln!(records, "{}", Record::new(headers, board));
+        }
+    });
+    written?;
+    records.flush()?;
+    fs::write(options.out.join("stats.txt"), calibration.to_string())?;
+    let stats = Stats {
+        seed: options.seed,
+        games: options.games,
+        strength: (0..bots.len()).map(|i| calibration.strength(i)).collect(),
+        calibration: &calibration,
+    };
+    fs::write(
+        options.out.join("stats.json"),
+        serde_json::to_string_pretty(&stats)?,
+    )?;
+    Ok(calibration)
+}
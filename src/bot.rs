@@ -0,0 +1,261 @@
+/// Computer opponents that can take a seat in a game
+use crate::profile::Profile;
+use crate::tictactoe::mcts::{self, MctsConfig, Position};
+use crate::tictactoe::tablebase::Tablebase;
+use crate::tictactoe::{best_next_move, Board, Difficulty};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use engine::ExternalEngine;
+use remote::RemoteBot;
+use serde::Serialize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub mod calibration;
+pub mod engine;
+pub mod remote;
+
+#[derive(Debug, Clone)]
+pub enum Bot {
+    Builtin(Difficulty),
+    /// Plays one of the best moves of the tablebase, never loses
+    Perfect,
+    /// Searches with random playouts, strength grows with the iterations
+    Mcts(MctsConfig),
+    /// A named bot that plays like another one, but slips now and then
+    Personality(Personality),
+    /// A program speaking the engine protocol, the builtin bot moves for it if it fails
+    External(Arc<ExternalEngine>),
+    /// A bot behind an HTTP callback, games ask it without holding up other games
+    Remote(Arc<RemoteBot>),
+}
+
+/// A bot players can pick by name
+#[derive(Debug, Clone, Serialize)]
+pub struct Personality {
+    /// The name it is picked with
+    pub id: &'static str,
+    pub name: &'static str,
+    pub avatar: &'static str,
+    /// How it plays when it does not blunder
+    #[serde(skip)]
+    pub base: Box<Bot>,
+    /// Chance of playing a random move instead, from 0 to 1
+    pub blunder: f64,
+}
+impl Personality {
+    pub fn new(
+        id: &'static str,
+        name: &'static str,
+        avatar: &'static str,
+        base: Bot,
+        blunder: f64,
+    ) -> Self {
+        Personality {
+            id,
+            name,
+            avatar,
+            base: Box::new(base),
+            blunder: blunder.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// The bots players can pick, weakest first
+pub fn personalities() -> Vec<Personality> {
+    [
+        (
+            "rookie",
+            "Rookie",
+            "🐣",
+            Bot::Builtin(Difficulty::easiest()),
+            0.0,
+        ),
+        (
+            "casual",
+            "Casual",
+            "🙂",
+            Bot::Builtin(Difficulty::beatable()),
+            0.1,
+        ),
+        (
+            "club",
+            "Club player",
+            "😎",
+            Bot::Builtin(Difficulty::medium()),
+            0.1,
+        ),
+        (
+            "expert",
+            "Expert",
+            "🧐",
+            Bot::Builtin(Difficulty::hardest()),
+            0.05,
+        ),
+        ("master", "Master", "🧙", Bot::Perfect, 0.0),
+    ]
+    .into_iter()
+    .map(|(id, name, avatar, base, blunder)| Personality::new(id, name, avatar, base, blunder))
+    .collect()
+}
+
+/// A personality with the share of points it scored in calibration
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibratedPersonality {
+    #[serde(flatten)]
+    pub personality: Personality,
+    pub strength: f64,
+}
+
+/// The bots players can pick with their measured strength, calibrating them on first use
+pub fn calibrated_personalities() -> Vec<CalibratedPersonality> {
+    let calibration = calibration::Calibration::personalities();
+    personalities()
+        .into_iter()
+        .enumerate()
+        .map(|(i, personality)| CalibratedPersonality {
+            personality,
+            strength: calibration.strength(i),
+        })
+        .collect()
+}
+
+impl Bot {
+    /// Pick the field to play next, the board is expected to be undecided
+    pub fn choose_move(&self, board: &Board) -> usize {
+        self.choose_move_with(board, &mut rand::thread_rng())
+    }
+    /// Like `choose_move`, drawing the bot's random choices from `rng`
+    pub fn choose_move_with(&self, board: &Board, rng: &mut impl Rng) -> usize {
+        match self {
+            Bot::Builtin(lvl) => best_next_move(board, lvl, rng),
+            Bot::Perfect => Tablebase::get()
+                .lookup(board)
+                .and_then(|entry| entry.best_moves().choose(rng).copied())
+                .unwrap_or_else(|| best_next_move(board, &Difficulty::hardest(), rng)),
+            Bot::Mcts(config) => mcts::search(board, config).unwrap_or(11),
+            Bot::Personality(p) if rng.gen_bool(p.blunder) => {
+                board.legal_moves().choose(rng).copied().unwrap_or(11)
+            }
+            Bot::Personality(p) => p.base.choose_move_with(board, rng),
+            Bot::External(engine) => engine.best_move(board).unwrap_or_else(|e| {
+                log::warn!("Engine {} failed: {e}", engine.name());
+                best_next_move(board, &Difficulty::hardest(), rng)
+            }),
+            Bot::Remote(remote) => remote.best_move(board).unwrap_or_else(|e| {
+                log::warn!("Remote bot {} failed: {e}", remote.name());
+                best_next_move(board, &Difficulty::hardest(), rng)
+            }),
+        }
+    }
+    /// The same bot with its own seed replaced, for bots that keep one
+    pub fn with_seed(self, seed: u64) -> Bot {
+        match self {
+            Bot::Mcts(config) => Bot::Mcts(MctsConfig { seed, ..config }),
+            Bot::Personality(mut p) => {
+                p.base = Box::new(p.base.with_seed(seed));
+                Bot::Personality(p)
+            }
+            bot => bot,
+        }
+    }
+    /// How the bot presents itself to its opponent
+    pub fn profile(&self) -> Profile {
+        let (name, avatar) = match self {
+            Bot::Personality(p) => (p.name, p.avatar),
+            Bot::External(engine) => (engine.name(), "⚙️"),
+            Bot::Remote(remote) => (remote.name(), "📡"),
+            _ => ("Bot", "🤖"),
+        };
+        Profile {
+            name: Some(name.into()),
+            avatar: Some(avatar.into()),
+            user_id: None,
+        }
+    }
+}
+
+/// Bots by the names players pick them with, "builtin:<level>" picks a level of
+/// `Difficulty` and "mcts:<iterations>" sets the iterations of the search
+impl FromStr for Bot {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let s = s.to_lowercase();
+        let (name, option) = match s.split_once(':') {
+            Some((name, option)) => (name, Some(option)),
+            None => (s.as_str(), None),
+        };
+        match (name, option) {
+            ("builtin", None) => Ok(Bot::Builtin(Difficulty::hardest())),
+            ("builtin", Some(level)) => level.parse().map(Bot::Builtin),
+            ("perfect", None) => Ok(Bot::Perfect),
+            ("mcts", iterations) => Ok(Bot::Mcts(MctsConfig {
+                iterations: match iterations {
+                    Some(i) => i.parse().map_err(|_| ())?,
+                    None => MctsConfig::default().iterations,
+                },
+                seed: rand::random(),
+                ..Default::default()
+            })),
+            (name, None) => personalities()
+                .into_iter()
+                .find(|p| p.id == name)
+                .map(Bot::Personality)
+                .ok_or(()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tictactoe::{Field, Player};
+
+    #[test]
+    fn builtin_bot_finishes_a_game_against_itself() {
+        let bot = Bot::Builtin(Difficulty::hardest());
+        let mut board = Board::new();
+        while board.get_winner().is_none() {
+            assert!(board.add_turn(bot.choose_move(&board)));
+        }
+    }
+
+    #[test]
+    fn bots_are_picked_by_name() {
+        assert!(matches!("Perfect".parse(), Ok(Bot::Perfect)));
+        assert!(matches!("mcts".parse(), Ok(Bot::Mcts(_))));
+        match "Club".parse::<Bot>() {
+            Ok(bot @ Bot::Personality(_)) => {
+                assert_eq!(Some("Club player".into()), bot.profile().name)
+            }
+            other => panic!("{other:?}"),
+        }
+        assert!("grandmaster".parse::<Bot>().is_err());
+        assert!(
+            matches!("builtin:medium".parse(), Ok(Bot::Builtin(lvl)) if lvl == Difficulty::medium())
+        );
+        assert!(matches!(
+            "mcts:50".parse(),
+            Ok(Bot::Mcts(MctsConfig { iterations: 50, .. }))
+        ));
+        assert!("builtin:impossible".parse::<Bot>().is_err());
+    }
+
+    #[test]
+    fn perfect_bot_never_loses() {
+        for _ in 0..20 {
+            let mut board = Board::new();
+            let builtin = Bot::Builtin(Difficulty::hardest());
+            while board.get_winner().is_none() {
+                let bot = if board.next_turn == Player::X {
+                    &builtin
+                } else {
+                    &Bot::Perfect
+                };
+                assert!(board.add_turn(bot.choose_move(&board)));
+            }
+            assert_ne!(Some(Field::X), board.get_winner().map(|(f, _)| f));
+        }
+    }
+}
@@ -0,0 +1,199 @@
+/// Plays bots against each other, to measure how strong they are
+use super::{personalities, Bot};
+use crate::tictactoe::{Board, Player};
+use crate::tournament::Record;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Games per pairing and side when the personalities are calibrated
+const GAMES: usize = 200;
+/// Seed of the calibration of the personalities, so every start lists the same strengths
+const SEED: u64 = 42;
+
+/// Play a game from the empty board, the final board has no winner if a bot was stuck
+pub fn play(x: &Bot, o: &Bot, rng: &mut impl Rng) -> Board {
+    let mut board = Board::new();
+    while board.get_winner().is_none() {
+        let bot = if board.next_turn == Player::X { x } else { o };
+        if !board.add_turn(bot.choose_move_with(&board, rng)) {
+            break;
+        }
+    }
+    board
+}
+
+/// The winner of a game played by `play`, None if it was drawn
+pub fn winner(board: &Board) -> Option<Player> {
+    match board.get_winner() {
+        Some((field, _)) => Player::try_from(field).ok(),
+        // a bot that cannot find a move loses
+        None => Some(!board.next_turn),
+    }
+}
+
+fn count(record: &mut Record, won: Option<bool>) {
+    match won {
+        Some(true) => record.wins += 1,
+        Some(false) => record.losses += 1,
+        None => record.draws += 1,
+    }
+}
+
+/// Results of every bot against every other bot
+#[derive(Debug, Clone, Serialize)]
+pub struct Calibration {
+    pub names: Vec<String>,
+    /// The record of each bot against each other bot, playing either side equally often
+    pub results: Vec<Vec<Record>>,
+}
+impl Calibration {
+    /// Play `games` games for every pairing and side, the same seed plays the same games
+    ///
+    /// `on_game` is called with the bots playing X and O and the final board of every game.
+    pub fn run(
+        bots: &[(String, Bot)],
+        games: usize,
+        seed: u64,
+        mut on_game: impl FnMut(usize, usize, &Board),
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut results = vec![vec![Record::default(); bots.len()]; bots.len()];
+        for (i, (_, x)) in bots.iter().enumerate() {
+            for (j, (_, o)) in bots.iter().enumerate().filter(|(j, _)| *j != i) {
+                for _ in 0..games {
+                    let board = play(x, o, &mut rng);
+                    on_game(i, j, &board);
+                    let winner = winner(&board);
+                    count(&mut results[i][j], winner.map(|w| w == Player::X));
+                    count(&mut results[j][i], winner.map(|w| w == Player::O));
+                }
+            }
+        }
+        Calibration {
+            names: bots.iter().map(|(name, _)| name.clone()).collect(),
+            results,
+        }
+    }
+
+    /// The personalities of `personalities`, calibrated the first time they are asked for
+    pub fn personalities() -> &'static Calibration {
+        static CALIBRATION: OnceLock<Calibration> = OnceLock::new();
+        CALIBRATION.get_or_init(|| {
+            let bots: Vec<(String, Bot)> = personalities()
+                .into_iter()
+                .map(|p| (p.id.to_string(), Bot::Personality(p)))
+                .collect();
+            Calibration::run(&bots, GAMES, SEED, |_, _, _| {})
+        })
+    }
+
+    /// Share of the points a bot scored against all others, from 0 to 1
+    pub fn strength(&self, bot: usize) -> f64 {
+        let total = self.results[bot]
+            .iter()
+            .fold(Record::default(), |total, r| Record {
+                wins: total.wins + r.wins,
+                draws: total.draws + r.draws,
+                losses: total.losses + r.losses,
+            });
+        let games = total.wins + total.draws + total.losses;
+        if games == 0 {
+            return 0.5;
+        }
+        total.points() / games as f64
+    }
+
+    /// Whether every bot is stronger than the ones before it
+    pub fn is_ordered(&self) -> bool {
+        (1..self.names.len()).all(|i| self.strength(i) > self.strength(i - 1))
+    }
+}
+
+/// A table of wins, draws and losses, one row per bot
+impl Display for Calibration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .names
+            .iter()
+            .map(|n| n.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(11);
+        write!(f, "{:<width$}", "")?;
+        for name in &self.names {
+            write!(f, " {name:>width$}")?;
+        }
+        writeln!(f, " {:>9}", "strength")?;
+        for (i, name) in self.names.iter().enumerate() {
+            write!(f, "{name:<width$}")?;
+            for (j, r) in self.results[i].iter().enumerate() {
+                if i == j {
+                    write!(f, " {:>width$}", "-")?;
+                } else {
+                    let record = format!("{}/{}/{}", r.wins, r.draws, r.losses);
+                    write!(f, " {record:>width$}")?;
+                }
+            }
+            writeln!(f, " {:>8.1}%", self.strength(i) * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::Personality;
+    use crate::tictactoe::Difficulty;
+
+    #[test]
+    fn difficulty_levels_are_ordered() {
+        let levels = [
+            Difficulty::easiest(),
+            Difficulty::easy(),
+            Difficulty::beatable(),
+            Difficulty::medium(),
+            Difficulty::hard(),
+            Difficulty::hardest(),
+        ];
+        let bots: Vec<(String, Bot)> = levels
+            .into_iter()
+            .enumerate()
+            .map(|(i, lvl)| (format!("level {i}"), Bot::Builtin(lvl)))
+            .collect();
+        let calibration = Calibration::run(&bots, 300, 7, |_, _, _| {});
+        assert!(calibration.is_ordered(), "\n{calibration}");
+    }
+
+    #[test]
+    fn personalities_are_ordered() {
+        let calibration = Calibration::personalities();
+        assert!(calibration.is_ordered(), "\n{calibration}");
+        // the master never loses
+        let master = calibration.names.len() - 1;
+        assert!(calibration.results[master].iter().all(|r| r.losses == 0));
+    }
+
+    #[test]
+    fn seeded_runs_repeat() {
+        let sloppy = Personality::new("sloppy", "Sloppy", "🙃", Bot::Perfect, 0.3);
+        let bots = vec![
+            ("perfect".to_string(), Bot::Perfect),
+            ("sloppy".to_string(), Bot::Personality(sloppy)),
+            ("casual".to_string(), "casual".parse().unwrap()),
+            ("random".to_string(), Bot::Builtin(Difficulty::easiest())),
+        ];
+        let run = |seed| {
+            let mut games = Vec::new();
+            Calibration::run(&bots, 20, seed, |_, _, board| {
+                games.push(board.history().to_vec())
+            });
+            games
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+}
@@ -0,0 +1,699 @@
+/// A text protocol for engines talking over stdin and stdout, modelled after chess UCI
+///
+/// ```text
+/// > tttp
+/// < id name Builtin
+/// < id author multiplayer-tictactoe
+/// < tttpok
+/// > isready
+/// < readyok
+/// > newgame
+/// > isready
+/// > position startpos moves b2 a1
+/// > go movetime 1000
+/// < readyok
+/// < info score win 5
+/// < bestmove c3
+/// > quit
+/// ```
+///
+/// Lines starting with ">" are sent to the engine, lines starting with "<" are its replies.
+/// Positions are "startpos" or a position in `Board::to_notation`, followed by the moves
+/// played since in record notation. Engines ignore commands they do not know, and answer
+/// "bestmove none" if the game is over. The server asks "isready" before every position and
+/// ignores what the engine says before "readyok", so a move that came too late is not mistaken
+/// for the next one.
+use super::Bot;
+use crate::tictactoe::analysis::Value;
+use crate::tictactoe::record::{cell_name, parse_cell};
+use crate::tictactoe::tablebase::Tablebase;
+use crate::tictactoe::Board;
+use rand::Rng;
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command as Process, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, TryLockError};
+use std::time::{Duration, Instant};
+
+/// How long an engine gets to introduce itself
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time an engine may take beyond its move time, for starting up and talking
+const GRACE: Duration = Duration::from_millis(500);
+/// How many engines may run at once, each one is a process of its own
+pub const MAX_ENGINES: usize = 16;
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    Unknown(String),
+    BadPosition(String),
+    BadCell(String),
+    BadNumber(String),
+}
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(l) => write!(f, "Unknown line: {l}"),
+            Self::BadPosition(p) => write!(f, "Bad position: {p}"),
+            Self::BadCell(c) => write!(f, "Not a cell: {c}"),
+            Self::BadNumber(n) => write!(f, "Not a number: {n}"),
+        }
+    }
+}
+impl std::error::Error for ProtocolError {}
+
+/// What the server tells an engine
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Asks the engine to introduce itself
+    Tttp,
+    IsReady,
+    NewGame,
+    Position(Board),
+    /// Asks for a move, to be found within the move time if there is one
+    Go {
+        movetime: Option<Duration>,
+    },
+    Quit,
+}
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tttp => write!(f, "tttp"),
+            Self::IsReady => write!(f, "isready"),
+            Self::NewGame => write!(f, "newgame"),
+            Self::Position(board) => {
+                let start = board.start();
+                if start == Board::new() {
+                    write!(f, "position startpos")?;
+                } else {
+                    write!(f, "position {}", start.to_notation())?;
+                }
+                if !board.history().is_empty() {
+                    write!(f, " moves")?;
+                    for cell in board.history() {
+                        write!(f, " {}", cell_name(*cell))?;
+                    }
+                }
+                Ok(())
+            }
+            Self::Go { movetime: None } => write!(f, "go"),
+            Self::Go { movetime: Some(t) } => write!(f, "go movetime {}", t.as_millis()),
+            Self::Quit => write!(f, "quit"),
+        }
+    }
+}
+impl FromStr for Command {
+    type Err = ProtocolError;
+    fn from_str(line: &str) -> Result<Self, ProtocolError> {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("tttp"), None) => Ok(Command::Tttp),
+            (Some("isready"), None) => Ok(Command::IsReady),
+            (Some("newgame"), None) => Ok(Command::NewGame),
+            (Some("quit"), None) => Ok(Command::Quit),
+            (Some("go"), None) => Ok(Command::Go { movetime: None }),
+            (Some("go"), Some("movetime")) => {
+                let ms = words.next().unwrap_or_default();
+                let ms = ms
+                    .parse()
+                    .map_err(|_| ProtocolError::BadNumber(ms.into()))?;
+                Ok(Command::Go {
+                    movetime: Some(Duration::from_millis(ms)),
+                })
+            }
+            (Some("position"), Some(_)) => parse_position(line).map(Command::Position),
+            _ => Err(ProtocolError::Unknown(line.into())),
+        }
+    }
+}
+
+/// Parses "position startpos moves a1" or "position X.O/.../... x moves b2"
+fn parse_position(line: &str) -> Result<Board, ProtocolError> {
+    let rest = line
+        .trim()
+        .strip_prefix("position")
+        .unwrap_or_default()
+        .trim();
+    let (start, moves) = match rest.split_once("moves") {
+        Some((start, moves)) => (start.trim(), moves),
+        None => (rest, ""),
+    };
+    let mut board = match start {
+        "startpos" => Board::new(),
+        notation => Board::from_notation(notation)
+            .map_err(|e| ProtocolError::BadPosition(format!("{notation}: {e}")))?,
+    };
+    for name in moves.split_whitespace() {
+        let cell = parse_cell(name).map_err(|_| ProtocolError::BadCell(name.into()))?;
+        if !board.add_turn(cell) {
+            return Err(ProtocolError::BadPosition(format!("{name} is not legal")));
+        }
+    }
+    Ok(board)
+}
+
+/// What an engine tells the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    IdName(String),
+    IdAuthor(String),
+    TttpOk,
+    ReadyOk,
+    /// Anything the engine wants to say about its search, e.g. "score win 3"
+    Info(String),
+    /// None if there is no move to make
+    BestMove(Option<usize>),
+}
+impl Display for Reply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IdName(name) => write!(f, "id name {name}"),
+            Self::IdAuthor(author) => write!(f, "id author {author}"),
+            Self::TttpOk => write!(f, "tttpok"),
+            Self::ReadyOk => write!(f, "readyok"),
+            Self::Info(info) => write!(f, "info {info}"),
+            Self::BestMove(Some(cell)) => write!(f, "bestmove {}", cell_name(*cell)),
+            Self::BestMove(None) => write!(f, "bestmove none"),
+        }
+    }
+}
+impl FromStr for Reply {
+    type Err = ProtocolError;
+    fn from_str(line: &str) -> Result<Self, ProtocolError> {
+        let line = line.trim();
+        let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        match (word, rest) {
+            ("tttpok", "") => Ok(Reply::TttpOk),
+            ("readyok", "") => Ok(Reply::ReadyOk),
+            ("info", info) => Ok(Reply::Info(info.into())),
+            ("bestmove", "none") => Ok(Reply::BestMove(None)),
+            ("bestmove", cell) => {
+                // anything after the move, like UCI's "ponder", is ignored
+                let cell = cell.split_whitespace().next().unwrap_or_default();
+                parse_cell(cell)
+                    .map(|c| Reply::BestMove(Some(c)))
+                    .map_err(|_| ProtocolError::BadCell(cell.into()))
+            }
+            ("id", id) => match id.split_once(' ') {
+                Some(("name", name)) => Ok(Reply::IdName(name.trim().into())),
+                Some(("author", author)) => Ok(Reply::IdAuthor(author.trim().into())),
+                _ => Err(ProtocolError::Unknown(line.into())),
+            },
+            _ => Err(ProtocolError::Unknown(line.into())),
+        }
+    }
+}
+
+/// Speak the protocol for a bot until told to quit or the input ends
+pub fn serve(
+    bot: &Bot,
+    name: &str,
+    input: impl BufRead,
+    mut output: impl Write,
+    rng: &mut impl Rng,
+) -> std::io::Result<()> {
+    let mut board = Board::new();
+    for line in input.lines() {
+        let replies = match line?.parse::<Command>() {
+            Ok(Command::Tttp) => vec![
+                Reply::IdName(name.into()),
+                Reply::IdAuthor(env!("CARGO_PKG_NAME").into()),
+                Reply::TttpOk,
+            ],
+            Ok(Command::IsReady) => vec![Reply::ReadyOk],
+            Ok(Command::NewGame) => {
+                board = Board::new();
+                Vec::new()
+            }
+            Ok(Command::Position(b)) => {
+                board = b;
+                Vec::new()
+            }
+            Ok(Command::Go { .. }) if board.get_winner().is_some() => vec![Reply::BestMove(None)],
+            Ok(Command::Go { .. }) => {
+                let mut replies = Vec::new();
+                if let Some(entry) = Tablebase::get().lookup(&board) {
+                    let score = match entry.value {
+                        Value::Win(moves) => format!("score win {moves}"),
+                        Value::Draw => "score draw".into(),
+                        Value::Loss(moves) => format!("score loss {moves}"),
+                    };
+                    replies.push(Reply::Info(score));
+                }
+                replies.push(Reply::BestMove(Some(bot.choose_move_with(&board, rng))));
+                replies
+            }
+            Ok(Command::Quit) => return Ok(()),
+            // unknown commands are ignored, as in UCI
+            Err(_) => Vec::new(),
+        };
+        for reply in replies {
+            writeln!(output, "{reply}")?;
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum EngineError {
+    Io(std::io::Error),
+    /// The engine did not answer in time
+    Timeout,
+    /// The engine closed its output
+    Exited,
+    /// The engine answered something that does not belong in the protocol
+    Protocol(ProtocolError),
+    IllegalMove(Option<usize>),
+    /// No engine of this name is configured
+    NotConfigured(String),
+    /// As many engines as allowed are running already
+    Busy,
+}
+impl Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Could not talk to the engine: {e}"),
+            Self::Timeout => write!(f, "The engine did not answer in time"),
+            Self::Exited => write!(f, "The engine has exited"),
+            Self::Protocol(e) => write!(f, "The engine broke the protocol: {e}"),
+            Self::IllegalMove(Some(cell)) => write!(
+                f,
+                "The engine played {}, which is not legal",
+                cell_name(*cell)
+            ),
+            Self::IllegalMove(None) => write!(f, "The engine did not find a move"),
+            Self::NotConfigured(name) => write!(f, "No engine named {name}"),
+            Self::Busy => write!(f, "Too many engines are running"),
+        }
+    }
+}
+impl std::error::Error for EngineError {}
+impl From<std::io::Error> for EngineError {
+    fn from(e: std::io::Error) -> Self {
+        EngineError::Io(e)
+    }
+}
+
+/// A place among the running engines, given back once dropped
+#[derive(Debug)]
+struct Slot(&'static AtomicUsize);
+impl Slot {
+    fn take(running: &'static AtomicUsize, max: usize) -> Result<Slot, EngineError> {
+        running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .map(|_| Slot(running))
+            .map_err(|_| EngineError::Busy)
+    }
+}
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A running engine process, which is stopped once it is dropped
+#[derive(Debug)]
+struct Connection {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines the engine wrote, read on a thread of their own so reading can time out
+    lines: Receiver<String>,
+    /// The name the engine introduced itself with
+    name: Option<String>,
+    _slot: Slot,
+}
+impl Connection {
+    /// Start an engine and wait for it to introduce itself, unless `MAX_ENGINES` are running
+    fn start(program: &str, args: &[String]) -> Result<Self, EngineError> {
+        let slot = Slot::take(&RUNNING, MAX_ENGINES)?;
+        let mut child = Process::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(EngineError::Exited);
+            }
+        };
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        let mut connection = Connection {
+            child,
+            stdin,
+            lines,
+            name: None,
+            _slot: slot,
+        };
+        connection.send(&[Command::Tttp])?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            match connection.receive(deadline)? {
+                Reply::IdName(name) => connection.name = Some(name),
+                Reply::TttpOk => return Ok(connection),
+                _ => (),
+            }
+        }
+    }
+
+    fn best_move(&mut self, board: &Board, movetime: Duration) -> Result<usize, EngineError> {
+        self.send(&[
+            Command::IsReady,
+            Command::Position(board.clone()),
+            Command::Go {
+                movetime: Some(movetime),
+            },
+        ])?;
+        let deadline = Instant::now() + movetime + GRACE;
+        while self.receive(deadline)? != Reply::ReadyOk {}
+        loop {
+            if let Reply::BestMove(cell) = self.receive(deadline)? {
+                let mut next = board.clone();
+                return match cell {
+                    Some(cell) if next.add_turn(cell) => Ok(cell),
+                    _ => Err(EngineError::IllegalMove(cell)),
+                };
+            }
+        }
+    }
+
+    fn send(&mut self, commands: &[Command]) -> Result<(), EngineError> {
+        for command in commands {
+            writeln!(self.stdin, "{command}")?;
+        }
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// The next line the engine wrote, skipping lines that are not part of the protocol
+    fn receive(&self, deadline: Instant) -> Result<Reply, EngineError> {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(timeout) {
+                Ok(line) => match line.parse() {
+                    Ok(reply) => return Ok(reply),
+                    Err(e) => log::debug!("Engine {}: {e}", self.name.as_deref().unwrap_or("?")),
+                },
+                Err(RecvTimeoutError::Timeout) => return Err(EngineError::Timeout),
+                Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Exited),
+            }
+        }
+    }
+}
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "{}", Command::Quit);
+        let _ = self.stdin.flush();
+        // engines that do not quit on their own are not waited for
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// An engine playing a game, running as a child process while the game needs it
+#[derive(Debug)]
+pub struct ExternalEngine {
+    program: String,
+    args: Vec<String>,
+    name: String,
+    movetime: Duration,
+    /// The running process, started again for a rematch once stopped or after it failed
+    connection: Mutex<Option<Connection>>,
+    /// Whether the process is to be stopped once it answered
+    stopping: AtomicBool,
+}
+impl ExternalEngine {
+    /// Start an engine and wait for it to introduce itself, unless `MAX_ENGINES` are running
+    pub fn spawn(program: &str, args: &[String], movetime: Duration) -> Result<Self, EngineError> {
+        let connection = Connection::start(program, args)?;
+        Ok(ExternalEngine {
+            program: program.to_string(),
+            args: args.to_vec(),
+            name: connection.name.clone().unwrap_or_else(|| program.to_string()),
+            movetime,
+            connection: Mutex::new(Some(connection)),
+            stopping: AtomicBool::new(false),
+        })
+    }
+
+    /// Start the engine of this name from `TICTACTOE_ENGINES`, which lists engines as
+    /// `name=program args...` separated by ";"
+    pub fn configured(name: &str, movetime: Duration) -> Result<Self, EngineError> {
+        let engines = std::env::var("TICTACTOE_ENGINES").unwrap_or_default();
+        let command = engines
+            .split(';')
+            .filter_map(|e| e.split_once('='))
+            .find(|(n, _)| n.trim() == name)
+            .map(|(_, command)| {
+                command
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|command| !command.is_empty())
+            .ok_or_else(|| EngineError::NotConfigured(name.into()))?;
+        ExternalEngine::spawn(&command[0], &command[1..], movetime)
+    }
+
+    /// The name the engine introduced itself with
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Ask the engine for its move, which is checked to be legal. The process is started
+    /// again if it was stopped, and stopped for good if it fails, so an engine that timed out
+    /// is never asked again.
+    pub fn best_move(&self, board: &Board) -> Result<usize, EngineError> {
+        let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        self.stopping.store(false, Ordering::SeqCst);
+        if connection.is_none() {
+            *connection = Some(Connection::start(&self.program, &self.args)?);
+        }
+        let answer = match connection.as_mut() {
+            Some(running) => running.best_move(board, self.movetime),
+            None => Err(EngineError::Exited),
+        };
+        if answer.is_err() {
+            *connection = None;
+        }
+        drop(connection);
+        // asked to stop while thinking
+        if self.stopping.load(Ordering::SeqCst) {
+            self.stop();
+        }
+        answer
+    }
+
+    /// Stop the process, right away or once it answered if it is thinking
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        match self.connection.try_lock() {
+            Ok(mut connection) => *connection = None,
+            Err(TryLockError::Poisoned(e)) => *e.into_inner() = None,
+            Err(TryLockError::WouldBlock) => (),
+        }
+    }
+
+    /// Whether the process is running, which it is while it thinks
+    pub fn is_running(&self) -> bool {
+        match self.connection.try_lock() {
+            Ok(connection) => connection.is_some(),
+            Err(TryLockError::Poisoned(e)) => e.into_inner().is_some(),
+            Err(TryLockError::WouldBlock) => true,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn commands_round_trip() {
+        let mut board = Board::from_notation("X../.../... o").unwrap();
+        board.add_turn(4);
+        let commands = [
+            Command::Tttp,
+            Command::IsReady,
+            Command::NewGame,
+            Command::Position(Board::new()),
+            Command::Position(board),
+            Command::Go { movetime: None },
+            Command::Go {
+                movetime: Some(Duration::from_millis(250)),
+            },
+            Command::Quit,
+        ];
+        for command in commands {
+            assert_eq!(
+                Ok(command.clone()),
+                command.to_string().parse(),
+                "{command}"
+            );
+        }
+        let mut board = Board::new();
+        board.add_turn(4);
+        assert_eq!(
+            "position startpos moves b2",
+            Command::Position(board).to_string()
+        );
+        assert!(matches!(
+            "position startpos moves b2 b2".parse::<Command>(),
+            Err(ProtocolError::BadPosition(_))
+        ));
+        assert!("uci".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn replies_are_parsed() {
+        assert_eq!(Ok(Reply::BestMove(Some(8))), "bestmove c3".parse());
+        assert_eq!(
+            Ok(Reply::BestMove(Some(0))),
+            "bestmove a1 ponder b2".parse()
+        );
+        assert_eq!(Ok(Reply::BestMove(None)), "bestmove none".parse());
+        assert_eq!(
+            Ok(Reply::IdName("Deep Thought".into())),
+            "id name Deep Thought".parse()
+        );
+        assert_eq!(
+            Ok(Reply::Info("score draw".into())),
+            "info score draw".parse()
+        );
+        assert!(matches!(
+            "bestmove z9".parse::<Reply>(),
+            Err(ProtocolError::BadCell(_))
+        ));
+    }
+
+    #[test]
+    fn bots_speak_the_protocol() {
+        let input =
+            "tttp\nisready\nposition startpos moves a1 b2 b1\ngo movetime 100\nbogus\nquit\ngo\n";
+        let mut output = Vec::new();
+        let bot = Bot::Perfect;
+        serve(
+            &bot,
+            "Perfect",
+            input.as_bytes(),
+            &mut output,
+            &mut StdRng::seed_from_u64(1),
+        )
+        .unwrap();
+        let replies: Vec<Reply> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                Reply::IdName("Perfect".into()),
+                Reply::IdAuthor(env!("CARGO_PKG_NAME").into()),
+                Reply::TttpOk,
+                Reply::ReadyOk,
+                Reply::Info("score draw".into()),
+                // the only move that does not lose right away
+                Reply::BestMove(Some(2)),
+            ],
+            replies
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn external_engines_take_a_seat() {
+        // an engine that always answers with the center, whatever the position
+        let script = "while read command rest; do case $command in \
+            tttp) echo 'id name Center'; echo tttpok;; isready) echo readyok;; \
+            go) echo 'info string thinking'; echo 'bestmove b2';; \
+            quit) exit;; esac; done";
+        let engine = ExternalEngine::spawn(
+            "sh",
+            &["-c".into(), script.into()],
+            Duration::from_millis(100),
+        )
+        .unwrap();
+        assert_eq!("Center", engine.name());
+        assert_eq!(4, engine.best_move(&Board::new()).unwrap());
+        let mut board = Board::new();
+        board.add_turn(4);
+        assert!(matches!(
+            engine.best_move(&board),
+            Err(EngineError::IllegalMove(Some(4)))
+        ));
+    }
+
+    #[test]
+    fn engines_beyond_the_limit_are_refused() {
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        let first = Slot::take(&RUNNING, 2).unwrap();
+        let _second = Slot::take(&RUNNING, 2).unwrap();
+        assert!(matches!(Slot::take(&RUNNING, 2), Err(EngineError::Busy)));
+        drop(first);
+        assert!(Slot::take(&RUNNING, 2).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn silent_engines_time_out() {
+        let script = "while read command rest; do case $command in tttp) echo tttpok;; esac; done";
+        let engine = ExternalEngine::spawn(
+            "sh",
+            &["-c".into(), script.into()],
+            Duration::from_millis(10),
+        )
+        .unwrap();
+        assert!(matches!(
+            engine.best_move(&Board::new()),
+            Err(EngineError::Timeout)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn engines_that_time_out_are_started_again() {
+        // an engine that is too slow the first time and quick once started again
+        let marker = std::env::temp_dir().join(format!("tttp-slow-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let script = format!(
+            "while read command rest; do case $command in \
+            tttp) echo tttpok;; isready) echo readyok;; \
+            go) if [ -e {0} ]; then echo 'bestmove b2'; else touch {0}; sleep 0.8; echo 'bestmove a1'; fi;; \
+            quit) exit;; esac; done",
+            marker.display()
+        );
+        let engine =
+            ExternalEngine::spawn("sh", &["-c".into(), script], Duration::from_millis(10)).unwrap();
+        assert!(matches!(
+            engine.best_move(&Board::new()),
+            Err(EngineError::Timeout)
+        ));
+        assert!(!engine.is_running());
+        assert_eq!(4, engine.best_move(&Board::new()).unwrap());
+        assert!(engine.is_running());
+        engine.stop();
+        assert!(!engine.is_running());
+        // a rematch starts it again
+        assert_eq!(4, engine.best_move(&Board::new()).unwrap());
+        let _ = std::fs::remove_file(&marker);
+    }
+}
@@ -0,0 +1,389 @@
+/// Bots that live behind an HTTP callback and are asked for their move on their turn
+///
+/// On its turn the bot gets a POST with a JSON body like
+///
+/// ```text
+/// {"position": "X../.O./... x", "to_move": "X", "moves": ["a1", "b2"], "legal_moves": ["b1", ...]}
+/// ```
+///
+/// and answers with `{"move": "c3"}`. Callbacks must be plain HTTP on this machine, on one of the
+/// ports `TICTACTOE_REMOTE_PORTS` allows. Attempts that fail to connect, time out or get a server
+/// error are retried within the `RetryPolicy`, anything else that is not a legal move forfeits
+/// the game.
+use crate::profile::{self, MAX_NAME_LEN};
+use crate::tictactoe::mcts::Position;
+use crate::tictactoe::record::{cell_name, parse_cell};
+use crate::tictactoe::{Board, Player};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::io::Read;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Answers longer than this are not read
+const MAX_ANSWER: u64 = 4096;
+/// Ports callbacks may use unless `TICTACTOE_REMOTE_PORTS` lists others
+const DEFAULT_PORTS: &str = "9000-9999";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteError {
+    /// Not a plain HTTP URL on this machine
+    BadUrl(String),
+    BadName,
+    /// Every attempt failed, the last failure is given
+    Unreachable(String),
+    /// The answer is not a move
+    InvalidAnswer(String),
+    IllegalMove(usize),
+    /// Callbacks may not use this port
+    PortNotAllowed(String),
+    /// No more bots can be registered
+    Full,
+}
+impl Display for RemoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadUrl(url) => write!(f, "Callbacks must be http URLs on this machine: {url}"),
+            Self::BadName => write!(f, "Names are 1 to {MAX_NAME_LEN} characters, without markup"),
+            Self::Unreachable(e) => write!(f, "The bot could not be reached: {e}"),
+            Self::InvalidAnswer(a) => write!(f, "The bot did not answer with a move: {a}"),
+            Self::IllegalMove(cell) => {
+                write!(f, "The bot played {}, which is not legal", cell_name(*cell))
+            }
+            Self::PortNotAllowed(url) => write!(f, "Callbacks may not use the port of {url}"),
+            Self::Full => write!(f, "No more bots can be registered"),
+        }
+    }
+}
+impl std::error::Error for RemoteError {}
+
+/// How long a bot gets to answer and how often it is asked again
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Per attempt, for connecting and reading the answer
+    pub timeout: Duration,
+    pub attempts: u32,
+    /// Wait before the second attempt, doubled for every further one
+    pub backoff: Duration,
+    /// No attempt is started after this much time has passed since the first
+    pub deadline: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            timeout: Duration::from_secs(2),
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            deadline: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What a bot is told on its turn
+#[derive(Debug, Serialize)]
+pub struct MoveRequest {
+    pub position: String,
+    pub to_move: Player,
+    /// Every move of the game so far, in record notation
+    pub moves: Vec<String>,
+    pub legal_moves: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveAnswer {
+    #[serde(rename = "move")]
+    cell: String,
+}
+
+/// A bot registered with its callback URL
+#[derive(Debug)]
+pub struct RemoteBot {
+    name: String,
+    url: String,
+    policy: RetryPolicy,
+    agent: ureq::Agent,
+}
+impl RemoteBot {
+    pub fn new(name: &str, url: &str, policy: RetryPolicy) -> Result<Self, RemoteError> {
+        // names are shown to players like their own, so they follow the same rules
+        let name = name.trim();
+        if profile::sanitize_name(name).as_deref() != Some(name) {
+            return Err(RemoteError::BadName);
+        }
+        if !is_local(url) {
+            return Err(RemoteError::BadUrl(url.into()));
+        }
+        let agent = ureq::AgentBuilder::new()
+            .timeout(policy.timeout)
+            // a redirect could lead off this machine
+            .redirects(0)
+            .build();
+        Ok(RemoteBot {
+            name: name.into(),
+            url: url.into(),
+            policy,
+            agent,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Ask the bot for its move, blocking until it answers or the policy gives up
+    pub fn best_move(&self, board: &Board) -> Result<usize, RemoteError> {
+        let request = MoveRequest {
+            position: board.to_notation(),
+            to_move: board.next_turn,
+            moves: board.history().iter().map(|c| cell_name(*c)).collect(),
+            legal_moves: board.legal_moves().into_iter().map(cell_name).collect(),
+        };
+        let started = Instant::now();
+        let mut backoff = self.policy.backoff;
+        let mut attempt = 1;
+        let answer = loop {
+            match self.ask(&request) {
+                Ok(answer) => break answer,
+                Err(Retry(e))
+                    if attempt < self.policy.attempts
+                        && started.elapsed() + backoff < self.policy.deadline =>
+                {
+                    log::info!("Remote bot {} failed, retrying: {e}", self.name);
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(Retry(e)) => return Err(RemoteError::Unreachable(e)),
+            }
+        }?;
+        let cell =
+            parse_cell(answer.trim()).map_err(|_| RemoteError::InvalidAnswer(answer.clone()))?;
+        if !board.legal_moves().contains(&cell) {
+            return Err(RemoteError::IllegalMove(cell));
+        }
+        Ok(cell)
+    }
+
+    /// One attempt, failures worth another attempt are errors, anything else is the answer
+    fn ask(&self, request: &MoveRequest) -> Result<Result<String, RemoteError>, Retry> {
+        let body = serde_json::to_string(request).unwrap_or_default();
+        let response = match self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+        {
+            Ok(response) => response,
+            Err(ureq::Error::Status(code, _)) if code >= 500 || code == 429 => {
+                return Err(Retry(format!("status {code}")))
+            }
+            Err(ureq::Error::Status(code, _)) => {
+                return Ok(Err(RemoteError::InvalidAnswer(format!("status {code}"))))
+            }
+            Err(e) => return Err(Retry(e.to_string())),
+        };
+        let mut text = String::new();
+        if let Err(e) = response
+            .into_reader()
+            .take(MAX_ANSWER)
+            .read_to_string(&mut text)
+        {
+            return Err(Retry(e.to_string()));
+        }
+        Ok(serde_json::from_str::<MoveAnswer>(&text)
+            .map(|a| a.cell)
+            .map_err(|_| RemoteError::InvalidAnswer(text.chars().take(100).collect())))
+    }
+}
+
+/// A failed attempt that may be tried again
+struct Retry(String);
+
+/// Whether the port of a callback is one bots may listen on, `TICTACTOE_REMOTE_PORTS` lists
+/// them like "8000-8099,9000" and defaults to 9000-9999
+pub fn port_allowed(url: &str) -> bool {
+    let ports = std::env::var("TICTACTOE_REMOTE_PORTS").unwrap_or_else(|_| DEFAULT_PORTS.into());
+    port(url).is_some_and(|port| allows(&ports, port))
+}
+
+/// Whether a list like "8000-8099,9000" contains the port
+fn allows(ports: &str, port: u16) -> bool {
+    ports.split(',').any(|range| {
+        let (low, high) = range.split_once('-').unwrap_or((range, range));
+        match (low.trim().parse::<u16>(), high.trim().parse::<u16>()) {
+            (Ok(low), Ok(high)) => (low..=high).contains(&port),
+            _ => false,
+        }
+    })
+}
+
+/// The port of a plain HTTP URL, 80 unless it says otherwise
+fn port(url: &str) -> Option<u16> {
+    let rest = url.strip_prefix("http://")?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let port = match authority.strip_prefix('[') {
+        Some(v6) => v6.split_once(']')?.1.strip_prefix(':'),
+        None => authority.split_once(':').map(|(_, port)| port),
+    };
+    match port {
+        None => Some(80),
+        Some(port) => port.parse().ok(),
+    }
+}
+
+/// Whether a URL is plain HTTP to a loopback address
+fn is_local(url: &str) -> bool {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if authority.contains('@') {
+        return false;
+    }
+    let host = match authority.strip_prefix('[') {
+        // an IPv6 address, like [::1]:8000
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A server on a free local port that answers every request with the next of `answers`,
+    /// as status and body, and counts the requests it got
+    pub(crate) fn stub(answers: Vec<(u16, &'static str)>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/move", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for (stream, (status, body)) in listener.incoming().zip(answers) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(l) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = l.trim().parse().unwrap();
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+                let mut request = vec![0; length];
+                reader.read_exact(&mut request).unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                if status == 0 {
+                    // hang up without answering
+                    continue;
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        (url, requests)
+    }
+
+    pub(crate) fn quick() -> RetryPolicy {
+        RetryPolicy {
+            timeout: Duration::from_millis(500),
+            attempts: 3,
+            backoff: Duration::from_millis(1),
+            deadline: Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn only_allowed_ports_are_accepted() {
+        assert_eq!(Some(80), port("http://localhost/move"));
+        assert_eq!(Some(9000), port("http://localhost:9000/move"));
+        assert_eq!(Some(9000), port("http://[::1]:9000"));
+        assert_eq!(None, port("http://localhost:http/"));
+        assert!(allows("8000-8099,9000", 8050));
+        assert!(allows("8000-8099, 9000", 9000));
+        assert!(!allows("8000-8099,9000", 22));
+        assert!(!allows("", 80));
+    }
+
+    #[test]
+    fn only_local_http_callbacks_are_accepted() {
+        assert!(is_local("http://localhost:8000/move"));
+        assert!(is_local("http://127.0.0.1/"));
+        assert!(is_local("http://[::1]:9000"));
+        assert!(!is_local("https://localhost/"));
+        assert!(!is_local("http://example.com/"));
+        assert!(!is_local("http://localhost@example.com/"));
+        assert!(!is_local("http://10.0.0.1/"));
+        assert!(matches!(
+            RemoteBot::new("bot", "http://example.com", quick()),
+            Err(RemoteError::BadUrl(_))
+        ));
+        assert!(matches!(
+            RemoteBot::new(" ", "http://localhost", quick()),
+            Err(RemoteError::BadName)
+        ));
+        for name in ["<b>bot</b>", "two  spaces", "tab\tbot", "a\u{202E}b", "a very long name for a bot"] {
+            assert!(
+                matches!(RemoteBot::new(name, "http://localhost", quick()), Err(RemoteError::BadName)),
+                "{name}"
+            );
+        }
+        assert!(RemoteBot::new(" Deep Thought ", "http://localhost", quick()).is_ok());
+    }
+
+    #[test]
+    fn moves_are_asked_for() {
+        let (url, requests) = stub(vec![(200, r#"{"move": "b2"}"#)]);
+        let bot = RemoteBot::new("stub", &url, quick()).unwrap();
+        assert_eq!(Ok(4), bot.best_move(&Board::new()));
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn failures_are_retried() {
+        let (url, requests) = stub(vec![(503, ""), (0, ""), (200, r#"{"move": "a1"}"#)]);
+        let bot = RemoteBot::new("stub", &url, quick()).unwrap();
+        assert_eq!(Ok(0), bot.best_move(&Board::new()));
+        assert_eq!(3, requests.load(Ordering::SeqCst));
+        let (url, requests) = stub(vec![(500, ""); 5]);
+        let bot = RemoteBot::new("stub", &url, quick()).unwrap();
+        assert!(matches!(
+            bot.best_move(&Board::new()),
+            Err(RemoteError::Unreachable(_))
+        ));
+        assert_eq!(3, requests.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn invalid_answers_are_not_retried() {
+        let mut board = Board::new();
+        board.add_turn(4);
+        for (answer, expected) in [
+            ((200, r#"{"move": "b2"}"#), RemoteError::IllegalMove(4)),
+            ((200, "b2"), RemoteError::InvalidAnswer("b2".into())),
+            (
+                (200, r#"{"move": "z9"}"#),
+                RemoteError::InvalidAnswer("z9".into()),
+            ),
+            ((404, ""), RemoteError::InvalidAnswer("status 404".into())),
+        ] {
+            let (url, requests) = stub(vec![answer, answer]);
+            let bot = RemoteBot::new("stub", &url, quick()).unwrap();
+            assert_eq!(Err(expected), bot.best_move(&board));
+            assert_eq!(1, requests.load(Ordering::SeqCst));
+        }
+    }
+}
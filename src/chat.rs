@@ -0,0 +1,192 @@
+/// Types and methods related to chatting within a single game
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Longest chat message we accept, in characters
+pub const MAX_MESSAGE_LEN: usize = 280;
+/// How many messages are kept around for late joiners
+pub const BACKLOG_LEN: usize = 50;
+/// How many messages a single sender may post within RATE_WINDOW
+pub const RATE_LIMIT: usize = 5;
+pub const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChatError {
+    EmptyMessage,
+    MessageTooLong,
+    RateLimited,
+    UnknownSender,
+}
+
+/// Players chat among themselves in front of everyone,
+/// spectators get a channel of their own that players don't see
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Players,
+    Spectators,
+}
+impl Channel {
+    /// Whether a player (or a spectator, if false) gets to read messages on this channel
+    pub fn visible_to(&self, is_player: bool) -> bool {
+        match self {
+            Channel::Players => true,
+            Channel::Spectators => !is_player,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub channel: Channel,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub text: String,
+}
+
+/// Counts recent actions per sender and refuses them once a limit is reached
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    history: HashMap<String, VecDeque<Instant>>,
+}
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        RateLimiter {
+            limit,
+            window,
+            history: HashMap::new(),
+        }
+    }
+    /// Records an action by `sender` at `now`, returns false if the sender is over the limit
+    pub fn allow(&mut self, sender: &str, now: Instant) -> bool {
+        let window = self.window;
+        let recent = self.history.entry(sender.to_string()).or_default();
+        while let Some(first) = recent.front() {
+            if now.duration_since(*first) >= window {
+                recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        if recent.len() >= self.limit {
+            false
+        } else {
+            recent.push_back(now);
+            true
+        }
+    }
+}
+
+/// Chat state of a game: a bounded backlog and a rate limit per sender
+#[derive(Debug, Clone)]
+pub struct Chat {
+    backlog: VecDeque<ChatMessage>,
+    limiter: RateLimiter,
+}
+impl Default for Chat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Chat {
+    pub fn new() -> Self {
+        Chat {
+            backlog: VecDeque::with_capacity(BACKLOG_LEN),
+            limiter: RateLimiter::new(RATE_LIMIT, RATE_WINDOW),
+        }
+    }
+    /// Validates and records a message, the returned message is what should be broadcast
+    pub fn post(
+        &mut self,
+        sender: &str,
+        channel: Channel,
+        name: String,
+        avatar: Option<String>,
+        text: &str,
+    ) -> Result<ChatMessage, ChatError> {
+        let text = sanitize_message(text)?;
+        if !self.limiter.allow(sender, Instant::now()) {
+            return Err(ChatError::RateLimited);
+        }
+        let msg = ChatMessage {
+            channel,
+            name,
+            avatar,
+            text,
+        };
+        if self.backlog.len() == BACKLOG_LEN {
+            self.backlog.pop_front();
+        }
+        self.backlog.push_back(msg.clone());
+        Ok(msg)
+    }
+    /// The messages a newly joined player or spectator is allowed to read, oldest first
+    pub fn backlog(&self, is_player: bool) -> Vec<ChatMessage> {
+        self.backlog
+            .iter()
+            .filter(|m| m.channel.visible_to(is_player))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Strips control characters and surrounding whitespace, enforces MAX_MESSAGE_LEN
+pub fn sanitize_message(raw: &str) -> Result<String, ChatError> {
+    let text: String = raw
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control())
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        Err(ChatError::EmptyMessage)
+    } else if text.chars().count() > MAX_MESSAGE_LEN {
+        Err(ChatError::MessageTooLong)
+    } else {
+        Ok(text.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_validated() {
+        assert_eq!(Ok("hi there".to_string()), sanitize_message("  hi\nthere\u{7} "));
+        assert_eq!(Err(ChatError::EmptyMessage), sanitize_message(" \t "));
+        assert_eq!(
+            Err(ChatError::MessageTooLong),
+            sanitize_message(&"a".repeat(MAX_MESSAGE_LEN + 1))
+        );
+    }
+
+    #[test]
+    fn rate_limit_is_per_sender_and_expires() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(limiter.allow("a", start));
+        assert!(limiter.allow("a", start));
+        assert!(!limiter.allow("a", start + Duration::from_secs(1)));
+        assert!(limiter.allow("b", start + Duration::from_secs(1)));
+        assert!(limiter.allow("a", start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn backlog_is_bounded_and_filtered() {
+        let mut chat = Chat::new();
+        chat.limiter = RateLimiter::new(usize::MAX, RATE_WINDOW);
+        for i in 0..BACKLOG_LEN {
+            chat.post("p", Channel::Players, "P".into(), None, &i.to_string())
+                .unwrap();
+        }
+        chat.post("s", Channel::Spectators, "S".into(), None, "psst")
+            .unwrap();
+        assert_eq!(BACKLOG_LEN - 1, chat.backlog(true).len());
+        assert_eq!(BACKLOG_LEN, chat.backlog(false).len());
+        assert_eq!("1", chat.backlog(true)[0].text);
+    }
+}
@@ -1,91 +1,469 @@
 /// Types and methods related to managing a single game
-
 use actix_web_lab::sse::{self, ChannelStream};
-use std::sync::{Arc, Mutex};
-use crate::tictactoe::{self, Board, Player};
-use serde::Serialize;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Instant, SystemTime};
+use crate::accounts::UserId;
+use crate::bot::engine::ExternalEngine;
+use crate::bot::remote::RemoteBot;
+use crate::bot::Bot;
+use crate::chat::{Channel, Chat, ChatError, RateLimiter};
+use crate::profile::Profile;
+use crate::protocol::ServerMessage;
+use crate::reaction::{Reaction, ReactionCounts, ReactionEvent, REACTION_LIMIT, REACTION_WINDOW};
+use crate::replay::{Audience, Replay};
+use crate::tictactoe::analysis::Solver;
+use crate::tictactoe::record::{self, Record};
+use crate::tictactoe::{self, Board, Difficulty, Player};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinError {
+    WrongPassphrase,
+}
+
+/// How many hints players get per game unless the creator says otherwise
+pub const DEFAULT_HINTS: usize = 3;
+/// Stands in for the winning line of a game won by forfeit
+pub const FORFEIT: usize = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HintError {
+    /// The game was created without hints
+    Disabled,
+    BudgetSpent,
+    /// Both seats have to be taken first
+    NotStarted,
+    NotYourTurn,
+    UnknownPlayer,
+    GameOver,
+}
+
+/// A suggested move, only ever sent to the player who asked for it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Hint {
+    pub cell: usize,
+    /// The cell in record notation, e.g. "b2"
+    pub name: String,
+    /// Hints the player has left in this game
+    pub remaining: usize,
+}
 
 #[derive(Debug, Clone, PartialEq)]
 enum GameError {
     MissingPlayer,
-    NoPlayers,
     PoisonedMutex,
 }
 
+/// How moves and events reach a player
 #[derive(Debug, Clone)]
+enum Connection {
+    Stream(sse::Sender),
+    Bot(Bot),
+}
+
+#[derive(Clone)]
 struct ActivePlayer {
-    stream: sse::Sender,
+    connection: Connection,
     credentials: String,
     score: usize,
+    profile: Profile,
+    /// The key this player took a reserved seat with
+    seat_key: Option<String>,
+}
+impl std::fmt::Debug for ActivePlayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActivePlayer")
+            .field("connection", &self.connection)
+            .field("credentials", &self.credentials)
+            .field("score", &self.score)
+            .field("profile", &self.profile)
+            .field("seat_key", &self.seat_key.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
 }
 impl ActivePlayer {
-    pub fn new(connection: sse::Sender) -> Self {
+    pub fn new(connection: sse::Sender, profile: Profile) -> Self {
         let cred = nanoid::nanoid!(12);
         ActivePlayer {  
-            stream: connection,
+            connection: Connection::Stream(connection),
             credentials: cred,
             score: 0,
+            profile,
+            seat_key: None,
+        }
+    }
+    pub fn bot(bot: Bot) -> Self {
+        ActivePlayer {
+            profile: bot.profile(),
+            connection: Connection::Bot(bot),
+            credentials: nanoid::nanoid!(12),
+            score: 0,
+            seat_key: None,
+        }
+    }
+    fn stream(&self) -> Option<&sse::Sender> {
+        match &self.connection {
+            Connection::Stream(stream) => Some(stream),
+            Connection::Bot(_) => None,
+        }
+    }
+    /// Sends an event to the player, bots ignore everything and are always reachable
+    async fn send(&self, msg: impl Into<sse::Event>) -> bool {
+        match &self.connection {
+            Connection::Stream(stream) => stream.send(msg).await.is_ok(),
+            Connection::Bot(_) => true,
         }
     }
     pub async fn ping(&self) -> bool {
-        self.stream
-            .send(sse::Event::Comment("ping".into()))
-            .await
-            .is_ok()
-    }
-    pub async fn notify(&self, msg: impl Into<&str>) -> bool {
-        let msg = self
-            .stream
-            .send(sse::Data::new(msg.into()).event("notification"))
-            .await;
-        msg.is_ok()
-    }
-    pub async fn send_credentials(&self) -> bool {
-        let msg = self
-            .stream
-            .send(sse::Data::new(self.credentials.clone()).event("credentials"))
-            .await;
-        msg.is_ok()
+        self.send(sse::Event::Comment("ping".into())).await
+    }
+    /// What others get to see about this player
+    fn info(&self, seat: Player) -> PlayerInfo {
+        PlayerInfo {
+            name: self.profile.display_name(seat),
+            avatar: self.profile.avatar.clone(),
+            registered: self.profile.user_id.is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Spectator {
+    stream: sse::Sender,
+    credentials: String,
+    profile: Profile,
+}
+impl Spectator {
+    pub fn new(connection: sse::Sender, profile: Profile) -> Self {
+        Spectator {
+            stream: connection,
+            credentials: nanoid::nanoid!(12),
+            profile,
+        }
+    }
+    fn name(&self) -> String {
+        self.profile.name.clone().unwrap_or_else(|| "Spectator".into())
+    }
+}
+
+/// Options chosen when a game is created
+#[derive(Clone, Default)]
+pub struct GameSettings {
+    /// Public games are listed in the lobby
+    pub public: bool,
+    /// Needed to take a seat, and to watch unless there is a spectator passphrase
+    pub passphrase: Option<String>,
+    /// Needed to watch the game, the player passphrase works as well
+    pub spectator_passphrase: Option<String>,
+    /// Results count towards the ratings of registered players
+    pub rated: bool,
+    /// Only holders of these keys may take a seat, one seat per key
+    pub seat_keys: Option<[String; 2]>,
+    /// Where the game (and every rematch) starts, the empty board if None
+    pub position: Option<Board>,
+    /// How many hints each player may ask for per game, none if 0
+    pub hints: usize,
+    /// Takes the seat of Player O right away
+    pub bot: Option<Bot>,
+    /// Seeds the random choices of bots, the same seed and moves replay a game exactly;
+    /// random if None
+    pub seed: Option<u64>,
+}
+/// Leaves out the passphrases and keys, games end up in the logs
+impl std::fmt::Debug for GameSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GameSettings")
+            .field("public", &self.public)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "<redacted>"))
+            .field("spectator_passphrase", &self.spectator_passphrase.as_ref().map(|_| "<redacted>"))
+            .field("rated", &self.rated)
+            .field("seat_keys", &self.seat_keys.as_ref().map(|_| "<redacted>"))
+            .field("position", &self.position)
+            .field("hints", &self.hints)
+            .field("bot", &self.bot)
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+/// How a finished game ended, reported once per game
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameResult {
+    /// Accounts of Player X and Player O, if they were logged in
+    pub accounts: [Option<UserId>; 2],
+    /// None for a draw
+    pub winner: Option<Player>,
+    pub rated: bool,
+    /// A bot took one of the seats
+    pub against_bot: bool,
+    /// Keys Player X and Player O took their reserved seats with
+    pub seat_keys: [Option<String>; 2],
+    /// How many hints Player X and Player O asked for
+    pub hints_used: [usize; 2],
+}
+impl GameResult {
+    /// Both players' accounts if this result should change their ratings
+    pub fn rated_accounts(&self) -> Option<(UserId, UserId)> {
+        match self.accounts {
+            [Some(x), Some(o)] if self.rated && !self.against_bot && x != o => Some((x, o)),
+            _ => None,
+        }
+    }
+    /// 1 if Player X won, 0 if Player O won, 0.5 for a draw
+    pub fn score_x(&self) -> f64 {
+        match self.winner {
+            Some(Player::X) => 1.0,
+            Some(Player::O) => 0.0,
+            None => 0.5,
+        }
+    }
+}
+
+/// Told the result of every board of a game as soon as it is decided, however it ended
+pub struct Reporter(Box<dyn Fn(GameResult) + Send + Sync>);
+impl Reporter {
+    pub fn new(report: impl Fn(GameResult) + Send + Sync + 'static) -> Self {
+        Reporter(Box::new(report))
+    }
+}
+impl std::fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Reporter")
     }
 }
 
 #[derive(Debug)]
 pub struct Game {
     inner: Mutex<GameInner>,
+    settings: GameSettings,
+    created: Instant,
+    created_at: SystemTime,
+    seed: u64,
+    reporter: OnceLock<Reporter>,
 }
 #[derive(Debug, Clone)]
 struct GameInner {
     pub board: tictactoe::Board,
+    /// Where bots draw their random choices from, seeded anew for every rematch
+    rng: StdRng,
     players: [Option<ActivePlayer>; 2],
-    spectators: Vec<sse::Sender>,
+    spectators: Vec<Spectator>,
     started: bool,
-
+    chat: Chat,
+    /// over the whole game, rematches included
+    reactions: ReactionCounts,
+    reaction_limiter: RateLimiter,
+    /// the result of the current board has been handed out
+    reported: bool,
+    /// The player who gave up the current board
+    forfeited: Option<Player>,
+    /// Counts rematches, to tell the boards of a game apart
+    round: usize,
+    /// Hints asked for from Player X and Player O on this board, a player who reconnects keeps theirs
+    hints_used: [usize; 2],
+    /// Numbers every event and keeps the recent ones for clients that reconnect
+    events: Replay,
+    /// The account that last sat in each seat, only they get its events replayed
+    occupants: [Option<UserId>; 2],
+}
+impl GameInner {
+    /// How the current board ended, a forfeit counts as a win for the other player
+    fn outcome(&self) -> Option<(tictactoe::Field, usize)> {
+        match self.forfeited {
+            Some(Player::X) => Some((tictactoe::Field::O, FORFEIT)),
+            Some(Player::O) => Some((tictactoe::Field::X, FORFEIT)),
+            None => self.board.get_winner(),
+        }
+    }
+    /// The seat belonging to these credentials, if any
+    fn seat_of(&self, cred: &str) -> Option<Player> {
+        if self.players[0].as_ref().map(|p| p.credentials == cred) == Some(true) {
+            Some(Player::X)
+        } else if self.players[1].as_ref().map(|p| p.credentials == cred) == Some(true) {
+            Some(Player::O)
+        } else {
+            None
+        }
+    }
+    /// Which channel, name and avatar a player or spectator with these credentials uses
+    fn sender(&self, cred: &str) -> Option<(Channel, String, Option<String>)> {
+        if let Some(seat) = self.seat_of(cred) {
+            let profile = &self.players[seat as usize].as_ref()?.profile;
+            Some((Channel::Players, profile.display_name(seat), profile.avatar.clone()))
+        } else {
+            self.spectators
+                .iter()
+                .find(|s| s.credentials == cred)
+                .map(|s| (Channel::Spectators, s.name(), s.profile.avatar.clone()))
+        }
+    }
+    /// Everyone currently connected to the game
+    fn streams(&self) -> Vec<sse::Sender> {
+        self.players
+            .iter()
+            .flatten()
+            .filter_map(|p| p.stream().cloned())
+            .chain(self.spectators.iter().map(|s| s.stream.clone()))
+            .collect()
+    }
+    fn gameinfo(&self) -> GameInfo {
+        GameInfo {
+            gamestate: self.board.show(),
+            outcome: self.outcome(),
+            players: [
+                self.players[0].as_ref().map(|p| p.info(Player::X)),
+                self.players[1].as_ref().map(|p| p.info(Player::O)),
+            ],
+            reactions: self.reactions.clone(),
+        }
+    }
 }
 impl Game {
     pub fn new() -> Arc<Self> {
+        Game::with_settings(GameSettings::default())
+    }
+
+    pub fn with_settings(settings: GameSettings) -> Arc<Self> {
+        let board = settings.position.clone().unwrap_or_default();
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        let bot = settings.bot.clone().map(|b| ActivePlayer::bot(b.with_seed(seed)));
         Arc::new(Game {
+            settings,
+            created: Instant::now(),
+            created_at: SystemTime::now(),
+            seed,
+            reporter: OnceLock::new(),
             inner: Mutex::new(GameInner {
-                board: tictactoe::Board::new(),
-                players: [None, None],
+                board,
+                rng: StdRng::seed_from_u64(seed),
+                players: [None, bot],
                 spectators: Vec::new(),
                 started: false,
-
+                chat: Chat::new(),
+                reactions: ReactionCounts::default(),
+                reaction_limiter: RateLimiter::new(REACTION_LIMIT, REACTION_WINDOW),
+                reported: false,
+                forfeited: None,
+                round: 0,
+                hints_used: [0; 2],
+                events: Replay::new(),
+                occupants: [None; 2],
             }),
         })
     }
 
-    ///True if a game has no players or the mutex is poisoned
+    /// A game where `seat` is taken by a computer opponent
+    pub fn with_bot(bot: Bot, seat: Player) -> Arc<Self> {
+        let game = Game::new();
+        game.inner.lock().unwrap().players[seat as usize] = Some(ActivePlayer::bot(bot.with_seed(game.seed)));
+        game
+    }
+
+    /// The seed bots draw their random choices from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.settings.public
+    }
+
+    /// Whether someone giving this passphrase may take a seat
+    fn may_play(&self, given: Option<&str>) -> bool {
+        match &self.settings.seat_keys {
+            Some(keys) => keys.iter().any(|k| given == Some(k.as_str())),
+            None => self.passphrase_ok(given),
+        }
+    }
+
+    /// Whether someone giving this passphrase may watch
+    pub fn may_watch(&self, given: Option<&str>) -> bool {
+        match &self.settings.spectator_passphrase {
+            Some(s) => {
+                given == Some(s.as_str())
+                    || (self.settings.passphrase.is_some() && self.passphrase_ok(given))
+                    || (self.settings.seat_keys.is_some() && self.may_play(given))
+            }
+            None => self.passphrase_ok(given),
+        }
+    }
+
+    fn passphrase_ok(&self, given: Option<&str>) -> bool {
+        match &self.settings.passphrase {
+            None => true,
+            Some(p) => given == Some(p.as_str()),
+        }
+    }
+
+    /// True if a player already sits on the seat reserved by this key
+    fn key_seated(&self, given: Option<&str>) -> bool {
+        match (&self.settings.seat_keys, given, self.lock()) {
+            (Some(_), Some(key), Ok(g)) => g
+                .players
+                .iter()
+                .flatten()
+                .any(|p| p.seat_key.as_deref() == Some(key)),
+            _ => false,
+        }
+    }
+
+    /// True if joining this game takes a passphrase
+    pub fn is_protected(&self) -> bool {
+        self.settings.passphrase.is_some() || self.settings.spectator_passphrase.is_some()
+    }
+
+    /// How long ago the game was created
+    pub fn age(&self) -> std::time::Duration {
+        self.created.elapsed()
+    }
+
+    /// The seat keys of those who took their seats, as long as the game waits for its players
+    pub fn seated_keys(&self) -> Option<Vec<String>> {
+        let g = self.lock().ok()?;
+        if g.started || g.outcome().is_some() {
+            return None;
+        }
+        Some(g.players.iter().flatten().filter_map(|p| p.seat_key.clone()).collect())
+    }
+
+    /// What the lobby shows about this game, seats are counted without pinging
+    pub fn summary(&self, gameid: &str) -> Option<GameSummary> {
+        let g = self.lock().ok()?;
+        let seats_taken = g.players.iter().flatten().count();
+        Some(GameSummary {
+            gameid: gameid.to_string(),
+            url: format!("/{gameid}/game"),
+            status: if seats_taken < 2 { GameStatus::Waiting } else { GameStatus::Playing },
+            seats_taken,
+            seats_free: 2 - seats_taken,
+            spectators: g.spectators.len(),
+            protected: self.is_protected(),
+            players: [
+                g.players[0].as_ref().map(|p| p.info(Player::X)),
+                g.players[1].as_ref().map(|p| p.info(Player::O)),
+            ],
+            age: self.created.elapsed().as_secs(),
+        })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, GameInner>, GameError> {
+        self.inner.lock().map_err(|_| GameError::PoisonedMutex)
+    }
+
+    ///True if a game has no human players or the mutex is poisoned
     pub async fn is_dead(&self) -> bool {
-        let g = match self.inner.lock() {
-            Ok(g) => g,
+        let players = match self.lock() {
+            Ok(g) => g.players.clone(),
             Err(_) => { return true;}
         };
-        let p1 = match &g.players[0] {
-            Some(p) => p.ping().await,
+        let p1 = match &players[0] {
+            Some(p) if p.stream().is_some() => p.ping().await,
             _ => false
         };
-        let p2 = match &g.players[1] {
-            Some(p) => p.ping().await,
+        let p2 = match &players[1] {
+            Some(p) if p.stream().is_some() => p.ping().await,
             _ => false
         };
         !(p1 || p2)
@@ -95,14 +473,7 @@ impl Game {
         if !self.healtchcheck().await? {
             return Err(GameError::MissingPlayer);
         }
-        let g = match self.inner.lock() {
-            Ok(guard) => guard,
-            Err(e) => {
-                return Err(GameError::PoisonedMutex);
-            }
-        };
-        let players = g.players.clone();
-        drop(g);
+        let players = self.lock()?.players.clone();
         if players[0].is_some() && players[1].is_some() {
             Ok([players[0].clone().unwrap(),players[1].clone().unwrap()])
         } else {
@@ -118,77 +489,120 @@ impl Game {
             //self.inner.clear_poison();
             return Err(GameError::PoisonedMutex);
         }
-        let players = &mut self.inner.lock().unwrap().players;
-              
-        for player in players {
-            ready = ready && match player {
-                Some(p) => { 
+        let players = self.lock()?.players.clone();
+
+        for (i, player) in players.iter().enumerate() {
+            match player {
+                Some(p) => {
                     if !p.ping().await {
-                        *player = None;
-                        
-                        false
-                    } else { true } 
+                        // only drop the seat if nobody else took it in the meantime
+                        let mut g = self.lock()?;
+                        if g.players[i].as_ref().map(|q| q.credentials == p.credentials) == Some(true) {
+                            g.players[i] = None;
+                        }
+                        ready = false;
+                    }
                 },
                 None => {
-                    
-                    false
+                    ready = false;
                 }
             };
         }
-        
-        Ok(ready)       
+
+        Ok(ready)
     }
     
     async fn check_ready(&self) {
-        let players = self.game_ok().await;
-        let mut g =  self.inner.lock().unwrap();
-        if players.is_ok() && !g.started {
-            g.started = true;
-            for i in 0..2 {
-                    if let Some(p) = &g.players[i] {
-                    p.stream.send(sse::Data::new("").event("startgame")).await;
-                    match i {
-                        0 => {p.notify("Game Ready, make the first move!").await;},
-                        1 => {p.notify("Game Ready! You are Player O, wait for your opponents move.").await;},
-                        _ => ()
-                    }
-                }             
-            }
+        let players = match self.game_ok().await {
+            Ok(p) => p,
+            Err(_) => { return; }
+        };
+        match self.lock() {
+            Ok(mut g) if !g.started => { g.started = true; }
+            _ => { return; }
+        }
+        let start = self.kept(Audience::Players, ServerMessage::StartGame);
+        for p in &players {
+            p.send(start.clone()).await;
         }
+        self.broadcast_state().await;
+        players[0].send(self.notification(Player::X, "Game Ready, make the first move!")).await;
+        players[1].send(self.notification(Player::O, "Game Ready! You are Player O, wait for your opponents move.")).await;
+        self.play_bots().await;
     }
     ///Add a player to the game, if there are two players present add spectator 
-    pub async fn join(&self) -> sse::Sse<ChannelStream> {
+    ///Protected games refuse anyone without the right passphrase
+    pub async fn join(&self, profile: Profile, passphrase: Option<&str>) -> Result<sse::Sse<ChannelStream>, JoinError> {
+        self.rejoin(profile, passphrase, None).await
+    }
+    ///Join like `join`, a client that reconnects with the id of the last event it saw
+    ///gets the events it missed instead of the chat backlog
+    pub async fn rejoin(&self, profile: Profile, passphrase: Option<&str>, last_event_id: Option<u64>) -> Result<sse::Sse<ChannelStream>, JoinError> {
+        let may_play = self.may_play(passphrase);
+        let may_watch = self.may_watch(passphrase);
+        if !may_play && !may_watch {
+            return Err(JoinError::WrongPassphrase);
+        }
         let (tx, rx) = sse::channel(30);
-        match self.healtchcheck().await {
-            Err(_) => {
-                false;
+        let stream = tx.clone();
+        let full = self.healtchcheck().await;
+        // a key fills one seat only, joining with it a second time means watching
+        let may_play = may_play && !self.key_seated(passphrase);
+        match full {
+            Err(_) => {}
+            Ok(false) if may_play => {
+                let seated = {
+                    let mut g = self.inner.lock().unwrap();
+                    let seat = if g.players[0].is_none() {
+                        Some(Player::X)
+                    } else if g.players[1].is_none() {
+                        Some(Player::O)
+                    } else {
+                        None
+                    };
+                    seat.map(|seat| {
+                        let mut p = ActivePlayer::new(tx, profile);
+                        if self.settings.seat_keys.is_some() {
+                            p.seat_key = passphrase.map(String::from);
+                        }
+                        // a player who is not logged in cannot be told apart from someone new
+                        if p.profile.user_id.is_none() || g.occupants[seat as usize] != p.profile.user_id {
+                            g.events.forget_seat(seat);
+                        }
+                        g.occupants[seat as usize] = p.profile.user_id;
+                        g.players[seat as usize] = Some(p.clone());
+                        (seat, p)
+                    })
+                };
+                if let Some((seat, p)) = seated {
+                    let replayed = self.replay(&stream, Some(seat), last_event_id).await;
+                    p.send(self.numbered(ServerMessage::Credentials(p.credentials.clone()))).await;
+                    p.send(self.numbered(ServerMessage::Notification(format!("You are {seat} in this game")))).await;
+                    if !replayed {
+                        self.send_chat_backlog(&stream, true, last_event_id.is_some()).await;
+                    }
+                }
             }
-            Ok(true) => {
-                tx.send(sse::Data::new("").event("startgame")).await;
+            Ok(_) if may_watch => {
+                let spectator = Spectator::new(tx, profile);
+                let replayed = self.replay(&stream, None, last_event_id).await;
+                if !replayed {
+                    let _ = spectator.stream.send(self.numbered(ServerMessage::StartGame)).await;
+                }
 
-                tx.send(sse::Data::new("You are a spectator in this game").event("notification")).await;
-                self.inner.lock().unwrap().spectators.push(tx);
-               
-            }
-            Ok(false) => {
-                let mut g = self.inner.lock().unwrap();
-                if g.players[0].is_none() {
-                    g.players[0] = Some(ActivePlayer::new(tx));
-                    if let Some(p) = &g.players[0] {
-                        p.send_credentials().await;
-                        p.notify("You are Player X in this game").await;
-                    }
-                } else if g.players[1].is_none() {
-                    g.players[1] = Some(ActivePlayer::new(tx));
-                    if let Some(p) = &g.players[1] {
-                        p.send_credentials().await;
-                        p.notify("You are Player O in this game").await;
-                    }
+                let _ = spectator.stream.send(self.numbered(ServerMessage::Credentials(spectator.credentials.clone()))).await;
+                let _ = spectator.stream.send(self.numbered(ServerMessage::Notification("You are a spectator in this game".into()))).await;
+                self.inner.lock().unwrap().spectators.push(spectator.clone());
+                if !replayed {
+                    self.send_chat_backlog(&stream, false, last_event_id.is_some()).await;
                 }
             }
+            Ok(_) => {
+                return Err(JoinError::WrongPassphrase);
+            }
         }
         self.check_ready().await;       
-        rx
+        Ok(rx)
     }
     /// start a new game, keep players
     pub async fn rematch(&self, cred: String) -> bool {
@@ -196,96 +610,485 @@ impl Game {
             Ok(true) => (),
             _ => { return false; }
         }
-        let mut g = self.inner.lock().unwrap();
-        if  !(g.players[0].as_ref().unwrap().credentials == cred || g.players[1].as_ref().unwrap().credentials == cred) {
-            return false;
+        {
+            let mut g = self.inner.lock().unwrap();
+            if !g.players.iter().flatten().any(|p| p.credentials == cred) {
+                return false;
+            }
+            g.players.swap(0, 1);
+            g.hints_used = [0; 2];
+            g.board = self.settings.position.clone().unwrap_or_default();
+            g.rng = StdRng::seed_from_u64(self.seed);
+            g.forfeited = None;
+            g.round += 1;
+            g.reported = false;
+            g.started = false;
         }
-        g.players.swap(0, 1);
-        g.board = tictactoe::Board::new();
-        g.started = false;
-        drop(g);
         self.check_ready().await;
         //self.show().await;
         true
     }
     /// add a move to the game
     pub async fn addmove(&self, newmove: usize, cred: String) -> bool {
-        log::info!("Move: {newmove}, Credentials: {cred}");
-        let mut players; 
-        if let Ok(p) = self.game_ok().await {
-            players = p;
+        log::info!("Move: {newmove}");
+        let players = if let Ok(p) = self.game_ok().await {
+            p
         } else {
             return false;
-        }
-        
-        let mut g = self.inner.lock().unwrap();
+        };
         
-        let board = &mut g.board;
-        let index = match board.next_turn {
-            tictactoe::Player::X => 0,
-            tictactoe::Player::O => 1,
+        let moved = {
+            let mut g = self.inner.lock().unwrap();
+            if g.forfeited.is_some() {
+                return false;
+            }
+
+            let board = &mut g.board;
+            let index = match board.next_turn {
+                tictactoe::Player::X => 0,
+                tictactoe::Player::O => 1,
+            };
+            players[index].credentials == cred && board.add_turn(newmove)
         };
-        if players[index].credentials == cred {
-            board.add_turn(newmove)
-        } else {
-            false
+        if moved {
+            self.report();
         }
-        
+        moved
     }
-    /// broadcast the game state
-    pub async fn show(&self) {
-        log::info!("Showing Game");
-        match self.inner.lock() {
-            Ok(g) => { 
-                let gameinfo = GameInfo {
-                gamestate: g.board.show(),
-                outcome: g.board.get_winner(),
-                };
-                let boardstate = serde_json::to_string(&gameinfo)
-                .unwrap();
-                let turn = match g.board.next_turn {
-                    tictactoe::Player::X => 0,
-                    tictactoe::Player::O => 1
-                };
-                for i in 0..2 {
-                    if let Some(p) = g.players[i].clone() {
-                        p.stream.send(sse::Data::new(boardstate.clone())).await;
-                        if gameinfo.outcome == None {
-                            if turn == i {
-                                p.notify(&*format!("Your turn, {}!", g.board.next_turn)).await;
-                            } else {
-                                p.notify("Wait for your opponent").await;
-                            } 
-                        }                      
+    #[cfg(test)]
+    pub(crate) fn credentials_of(&self, seat: Player) -> Option<String> {
+        self.lock().ok()?.players[seat as usize].as_ref().map(|p| p.credentials.clone())
+    }
+
+    /// Who gets told the results of this game, only the first one given counts
+    pub fn report_to(&self, reporter: Reporter) {
+        let _ = self.reporter.set(reporter);
+    }
+    /// Hand the result to the reporter if the board was just decided
+    fn report(&self) {
+        self.stop_engines();
+        if let Some(reporter) = self.reporter.get() {
+            if let Some(result) = self.take_result() {
+                (reporter.0)(result);
+            }
+        }
+    }
+    /// Stop the engines seated here once the board is decided, a rematch starts them again
+    fn stop_engines(&self) {
+        let engines: Vec<Arc<ExternalEngine>> = match self.lock() {
+            Ok(g) if g.outcome().is_some() => g
+                .players
+                .iter()
+                .flatten()
+                .filter_map(|p| match &p.connection {
+                    Connection::Bot(Bot::External(engine)) => Some(Arc::clone(engine)),
+                    _ => None,
+                })
+                .collect(),
+            _ => return,
+        };
+        for engine in engines {
+            engine.stop();
+        }
+    }
+    /// The result of a finished game, only handed out once per board
+    pub fn take_result(&self) -> Option<GameResult> {
+        let mut g = self.lock().ok()?;
+        if g.reported {
+            return None;
+        }
+        let (winner, _) = g.outcome()?;
+        g.reported = true;
+        Some(GameResult {
+            accounts: [
+                g.players[0].as_ref().and_then(|p| p.profile.user_id),
+                g.players[1].as_ref().and_then(|p| p.profile.user_id),
+            ],
+            winner: Player::try_from(winner).ok(),
+            rated: self.settings.rated,
+            against_bot: g.players.iter().flatten().any(|p| p.stream().is_none()),
+            seat_keys: [
+                g.players[0].as_ref().and_then(|p| p.seat_key.clone()),
+                g.players[1].as_ref().and_then(|p| p.seat_key.clone()),
+            ],
+            hints_used: g.hints_used,
+        })
+    }
+    /// Suggest the best move to the player whose turn it is, from a full search
+    pub fn hint(&self, cred: &str) -> Result<Hint, HintError> {
+        if self.settings.hints == 0 {
+            return Err(HintError::Disabled);
+        }
+        let (board, remaining) = {
+            let mut g = self.lock().map_err(|_| HintError::UnknownPlayer)?;
+            let seat = g.seat_of(cred).ok_or(HintError::UnknownPlayer)?;
+            if !g.started {
+                return Err(HintError::NotStarted);
+            }
+            if g.outcome().is_some() {
+                return Err(HintError::GameOver);
+            }
+            if g.board.next_turn != seat {
+                return Err(HintError::NotYourTurn);
+            }
+            let used = &mut g.hints_used[seat as usize];
+            if *used >= self.settings.hints {
+                return Err(HintError::BudgetSpent);
+            }
+            *used += 1;
+            let remaining = self.settings.hints - *used;
+            (g.board.clone(), remaining)
+        };
+        let best = Solver::new()
+            .moves(&board)
+            .into_iter()
+            .next()
+            .ok_or(HintError::GameOver)?;
+        Ok(Hint {
+            cell: best.cell,
+            name: best.name,
+            remaining,
+        })
+    }
+    /// The current board with its history
+    pub fn board(&self) -> Option<Board> {
+        self.lock().ok().map(|g| g.board.clone())
+    }
+    /// The board once it is decided, the values of the moves of a running game would give hints away
+    pub fn finished_board(&self) -> Option<Board> {
+        self.lock().ok().filter(|g| g.outcome().is_some()).map(|g| g.board.clone())
+    }
+    /// The game so far in record notation, players are named as they are shown
+    pub fn record(&self) -> Option<Record> {
+        let g = self.lock().ok()?;
+        let name = |seat: Player| {
+            g.players[seat as usize]
+                .as_ref()
+                .map(|p| p.profile.display_name(seat))
+                .unwrap_or_else(|| "?".into())
+        };
+        let mut headers = vec![
+            ("Event".into(), "Tic-Tac-Toe".into()),
+            ("Date".into(), record::date(self.created_at)),
+            ("X".into(), name(Player::X)),
+            ("O".into(), name(Player::O)),
+            ("Variant".into(), "Standard".into()),
+            ("Seed".into(), self.seed.to_string()),
+        ];
+        if let Some(loser) = g.forfeited {
+            headers.push(("Termination".into(), format!("{} forfeited", name(loser))));
+        }
+        if !g.reactions.is_empty() {
+            headers.push(("Reactions".into(), g.reactions.to_string()));
+        }
+        Some(Record::new(headers, &g.board))
+    }
+    /// Let bots make their moves for as long as it is their turn
+    pub async fn play_bots(&self) {
+        loop {
+            // bots that may take their time are asked outside the lock
+            let slow = match self.lock() {
+                Ok(mut g) if g.started && g.outcome().is_none() => {
+                    let g = &mut *g;
+                    match &g.players[g.board.next_turn as usize] {
+                        Some(ActivePlayer { connection: Connection::Bot(bot @ (Bot::Remote(_) | Bot::External(_))), credentials, .. }) => {
+                            Some((bot.clone(), credentials.clone(), g.board.clone(), g.round))
+                        }
+                        Some(ActivePlayer { connection: Connection::Bot(bot), .. }) => {
+                            let bot = bot.clone();
+                            let choice = bot.choose_move_with(&g.board, &mut g.rng);
+                            if !g.board.add_turn(choice) {
+                                return;
+                            }
+                            None
+                        }
+                        _ => { return; }
                     }
                 }
-
-                for spec in &g.spectators.clone() {
-                    spec.send(sse::Data::new(boardstate.clone())).await;
+                _ => { return; }
+            };
+            let played = match slow {
+                Some((Bot::Remote(remote), cred, board, round)) => self.play_remote(remote, cred, board, round).await,
+                Some((Bot::External(engine), cred, board, round)) => self.play_engine(engine, cred, board, round).await,
+                _ => true,
+            };
+            if !played {
+                return;
+            }
+            self.report();
+            self.show().await;
+        }
+    }
+    /// Ask a remote bot for its move without holding the game, it forfeits if it has none
+    async fn play_remote(&self, remote: Arc<RemoteBot>, cred: String, board: Board, round: usize) -> bool {
+        let moves = board.history().len();
+        let answer = actix_web::rt::task::spawn_blocking(move || remote.best_move(&board)).await;
+        if !self.still_at(round, moves) {
+            return false;
+        }
+        match answer {
+            Ok(Ok(cell)) => self.addmove(cell, cred).await,
+            Ok(Err(e)) => {
+                log::info!("Remote bot forfeits: {e}");
+                self.forfeit(&cred)
+            }
+            Err(_) => self.forfeit(&cred),
+        }
+    }
+    /// Ask an external engine for its move without holding the game, the builtin bot
+    /// moves instead if the engine fails
+    async fn play_engine(&self, engine: Arc<ExternalEngine>, cred: String, board: Board, round: usize) -> bool {
+        let moves = board.history().len();
+        let name = engine.name().to_string();
+        let answer = actix_web::rt::task::spawn_blocking(move || engine.best_move(&board)).await;
+        if !self.still_at(round, moves) {
+            return false;
+        }
+        let cell = match answer {
+            Ok(Ok(cell)) => cell,
+            failed => {
+                if let Ok(Err(e)) = failed {
+                    log::warn!("Engine {name} failed: {e}");
+                }
+                match self.lock() {
+                    Ok(mut g) => {
+                        let g = &mut *g;
+                        Bot::Builtin(Difficulty::hardest()).choose_move_with(&g.board, &mut g.rng)
+                    }
+                    Err(_) => { return false; }
                 }
-                log::info!("All Messages sent");
+            }
+        };
+        self.addmove(cell, cred).await
+    }
+    /// Whether the game is still undecided and at the move a bot was asked about,
+    /// someone may have moved on while it was thinking
+    fn still_at(&self, round: usize, moves: usize) -> bool {
+        matches!(self.lock(), Ok(g) if g.round == round && g.board.history().len() == moves && g.outcome().is_none())
+    }
+    /// The player with these credentials gives up the current board
+    pub fn forfeit(&self, cred: &str) -> bool {
+        let mut g = match self.lock() {
+            Ok(g) => g,
+            Err(_) => { return false; }
+        };
+        match g.seat_of(cred) {
+            Some(seat) if g.started && g.outcome().is_none() => {
+                g.forfeited = Some(seat);
+                drop(g);
+                self.report();
+                true
+            }
+            _ => false,
+        }
+    }
+    /// send the game state to players and spectators, without notifications
+    async fn broadcast_state(&self) -> Option<GameInfo> {
+        let (gameinfo, boardstate, players, spectators) = match self.lock() {
+            Ok(mut g) => {
+                let gameinfo = g.gameinfo();
+                let boardstate = g.events.record(Audience::Everyone, ServerMessage::State(gameinfo.clone()));
+                (gameinfo, boardstate, g.players.clone(), g.spectators.clone())
             }
             Err(e) => {
                 log::error!("Could not show Game due to {:?}", e);
+                return None;
+            }
+        };
+        for p in players.iter().flatten() {
+            p.send(boardstate.clone()).await;
+        }
+        let mut gone: Vec<String> = Vec::new();
+        for spec in &spectators {
+            if spec.stream.send(boardstate.clone()).await.is_err() {
+                gone.push(spec.credentials.clone());
+            }
+        }
+        if !gone.is_empty() {
+            if let Ok(mut g) = self.lock() {
+                g.spectators.retain(|s| !gone.contains(&s.credentials));
             }
         }
+        Some(gameinfo)
     }
+    /// Post a chat message, players talk on the players channel and spectators on their own
+    pub async fn chat(&self, cred: String, text: String) -> Result<(), ChatError> {
+        let (msg, recipients) = {
+            let mut g = self.lock().map_err(|_| ChatError::UnknownSender)?;
+            let (channel, name, avatar) = g.sender(&cred).ok_or(ChatError::UnknownSender)?;
+            let msg = g.chat.post(&cred, channel, name, avatar, &text)?;
+            let mut recipients: Vec<sse::Sender> = Vec::new();
+            if channel.visible_to(true) {
+                recipients.extend(g.players.iter().flatten().filter_map(|p| p.stream().cloned()));
+            }
+            if channel.visible_to(false) {
+                recipients.extend(g.spectators.iter().map(|s| s.stream.clone()));
+            }
+            (g.events.record(channel.into(), ServerMessage::Chat(msg)), recipients)
+        };
+        for stream in recipients {
+            let _ = stream.send(msg.clone()).await;
+        }
+        Ok(())
+    }
+    /// Fire a quick reaction, everyone in the game gets to see it
+    pub async fn react(&self, cred: String, reaction: Reaction) -> Result<(), ChatError> {
+        let (event, recipients) = {
+            let mut g = self.lock().map_err(|_| ChatError::UnknownSender)?;
+            let (_, name, _) = g.sender(&cred).ok_or(ChatError::UnknownSender)?;
+            if !g.reaction_limiter.allow(&cred, std::time::Instant::now()) {
+                return Err(ChatError::RateLimited);
+            }
+            g.reactions.add(reaction);
+            let event = ReactionEvent {
+                reaction,
+                emoji: reaction.emoji().into(),
+                name,
+            };
+            (g.events.record(Audience::Everyone, ServerMessage::Reaction(event)), g.streams())
+        };
+        for stream in recipients {
+            let _ = stream.send(event.clone()).await;
+        }
+        Ok(())
+    }
+    /// Sends a client that reconnected the events it missed,
+    /// false if it did not reconnect or the game no longer has all of them
+    async fn replay(&self, stream: &sse::Sender, seat: Option<Player>, last_event_id: Option<u64>) -> bool {
+        let missed = match (last_event_id, self.lock()) {
+            (Some(id), Ok(g)) => g.events.since(id, seat),
+            _ => None,
+        };
+        match missed {
+            Some(events) => {
+                for event in events {
+                    let _ = stream.send(event).await;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+    /// replay recent chat messages to someone who just joined,
+    /// with the board for a client that reconnected too late to get what it missed
+    async fn send_chat_backlog(&self, stream: &sse::Sender, is_player: bool, with_state: bool) {
+        let events: Vec<sse::Data> = match self.lock() {
+            Ok(mut g) => {
+                let mut events: Vec<sse::Data> = g.chat.backlog(is_player)
+                    .into_iter()
+                    .map(|msg| g.events.number(ServerMessage::Chat(msg)))
+                    .collect();
+                if with_state {
+                    let gameinfo = g.gameinfo();
+                    events.push(g.events.number(ServerMessage::State(gameinfo)));
+                }
+                events
+            }
+            Err(_) => { return; }
+        };
+        for event in events {
+            let _ = stream.send(event).await;
+        }
+    }
+    /// Numbers an event meant for one stream only
+    fn numbered(&self, message: ServerMessage) -> sse::Data {
+        match self.lock() {
+            Ok(mut g) => g.events.number(message),
+            Err(_) => message.into(),
+        }
+    }
+    /// Numbers an event and keeps it for those in `audience` who reconnect
+    fn kept(&self, audience: Audience, message: ServerMessage) -> sse::Data {
+        match self.lock() {
+            Ok(mut g) => g.events.record(audience, message),
+            Err(_) => message.into(),
+        }
+    }
+    /// A notification for the player in `seat`, sent again if they reconnect
+    fn notification(&self, seat: Player, text: &str) -> sse::Data {
+        self.kept(Audience::Seat(seat), ServerMessage::Notification(text.into()))
+    }
+    /// broadcast the game state
+    pub async fn show(&self) {
+        log::info!("Showing Game");
+        let gameinfo = match self.broadcast_state().await {
+            Some(info) => info,
+            None => { return; }
+        };
+        if gameinfo.outcome.is_some() {
+            log::info!("All Messages sent");
+            return;
+        }
+        let (players, next_turn) = match self.lock() {
+            Ok(g) => (g.players.clone(), g.board.next_turn),
+            Err(_) => { return; }
+        };
+        for (seat, player) in [Player::X, Player::O].into_iter().zip(players.iter()) {
+            if let Some(p) = player {
+                if next_turn == seat {
+                    p.send(self.notification(seat, &format!("Your turn, {}!", p.profile.display_name(next_turn)))).await;
+                } else {
+                    p.send(self.notification(seat, "Wait for your opponent")).await;
+                }
+            }
+        }
+        log::info!("All Messages sent");
+    }
+}
+
+/// The state of a game as players and spectators get it on every change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameInfo {
+    pub gamestate: [tictactoe::Field; 9],
+    /// The winner, or Empty for a draw, and how the game ended, see `Board::get_winner` and `FORFEIT`
+    pub outcome: Option<(tictactoe::Field, usize)>,
+    /// Player X and Player O, once they joined
+    pub players: [Option<PlayerInfo>; 2],
+    pub reactions: ReactionCounts,
 }
 
-#[derive(Debug, Serialize)]
-struct GameInfo {
-    gamestate: [tictactoe::Field; 9],
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameStatus {
+    /// Waiting for an opponent
+    Waiting,
+    /// Both seats taken, open to spectators
+    Playing,
+}
 
-    outcome: Option<(tictactoe::Field, usize)>,
+/// A game as listed in the lobby
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub gameid: String,
+    pub url: String,
+    pub status: GameStatus,
+    pub seats_taken: usize,
+    pub seats_free: usize,
+    pub spectators: usize,
+    /// Joining takes a passphrase
+    pub protected: bool,
+    pub players: [Option<PlayerInfo>; 2],
+    /// Seconds since the game was created
+    pub age: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub name: String,
+    pub avatar: Option<String>,
+    /// Playing with an account rather than anonymously
+    pub registered: bool,
 }
 
 #[cfg(test)]
 mod tests {
     
-    use actix_web::{body::{MessageBody, BodySize}, test, HttpRequest, HttpResponse, HttpMessage, Responder};
-    use actix_web::http::{header, StatusCode};
+    use actix_web::{body::{MessageBody, BodySize}, test, Responder};
 
     use super::*;
+    use crate::replay::REPLAY_LEN;
+    use crate::tictactoe::mcts::Position;
+    use std::time::Duration;
 
     #[actix_web::test]
     async fn game_with_no_players_is_dead() {
@@ -294,19 +1097,19 @@ mod tests {
         assert!(g.inner.lock().unwrap().players[1].is_none());
         //assert!(g.healtchcheck().await.is_err());
         assert!(g.is_dead().await);
-        let p1 = g.join().await;
+        let p1 = g.join(Profile::default(), None).await.unwrap();
         assert!(!g.is_dead().await);
         drop(p1);
-        g.healtchcheck();
+        let _ = g.healtchcheck().await;
         assert!(g.is_dead().await);
     }
 
     #[actix_web::test]
     async fn rematch_works() {
         let g = Game::new();
-        let s1 = g.join().await;
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
         assert_eq!(Ok(false), g.healtchcheck().await);
-        let s2 = g.join().await;
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
         assert_eq!(Ok(true), g.healtchcheck().await);
         let cred1 = g.inner.lock().as_ref().unwrap().players[0].as_ref().unwrap().credentials.clone();
         let cred2 = g.inner.lock().as_ref().unwrap().players[1].as_ref().unwrap().credentials.clone();
@@ -320,9 +1123,9 @@ mod tests {
     #[actix_web::test]
     async fn can_add_moves() {
         let g = Game::new();
-        let s1 = g.join().await;
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
         assert_eq!(Ok(false), g.healtchcheck().await);
-        let s2 = g.join().await;
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
         assert!(g.game_ok().await.is_ok());
         let players = g.game_ok().await.unwrap();
         assert!(g.addmove(0, players[0].credentials.clone()).await);  
@@ -330,7 +1133,7 @@ mod tests {
         assert!(g.addmove(6, players[1].credentials.clone()).await);
         assert!(g.addmove(1, players[0].credentials.clone()).await);
         assert!(g.addmove(7, players[1].credentials.clone()).await);
-        assert!(None == g.inner.lock().unwrap().board.get_winner());
+        assert!(g.inner.lock().unwrap().board.get_winner().is_none());
         assert!(g.addmove(2, players[0].credentials.clone()).await);
         assert!(Some((tictactoe::Field::X, 0)) == g.inner.lock().unwrap().board.get_winner());     
         assert!(!g.addmove(8, players[1].credentials.clone()).await);        
@@ -339,33 +1142,497 @@ mod tests {
     #[actix_web::test]
     async fn can_join_empty_game() {
         let g = Game::new();
-        let s1 = g.join().await;
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
         assert_eq!(Ok(false), g.healtchcheck().await);
-        let s2 = g.join().await;
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
         assert_eq!(Ok(true), g.healtchcheck().await);        
     }
 
+    #[actix_web::test]
+    async fn profiles_are_part_of_the_state() {
+        let g = Game::new();
+        let _s1 = g.join(Profile::new(Some("Alice"), Some("🦀")), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let info = g.inner.lock().unwrap().gameinfo();
+        let x = info.players[0].as_ref().unwrap();
+        let o = info.players[1].as_ref().unwrap();
+        assert_eq!("Alice", x.name);
+        assert_eq!(Some("🦀".to_string()), x.avatar);
+        assert_eq!("Player O", o.name);
+        assert_eq!(None, o.avatar);
+        assert!(!o.registered);
+    }
+
+    #[actix_web::test]
+    async fn chat_respects_channels() {
+        let g = Game::new();
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let _s3 = g.join(Profile::new(Some("Sam"), None), None).await.unwrap();
+        let (cred_x, cred_spec) = {
+            let inner = g.inner.lock().unwrap();
+            (inner.players[0].as_ref().unwrap().credentials.clone(), inner.spectators[0].credentials.clone())
+        };
+        assert_eq!(Ok(()), g.chat(cred_x, "good luck".into()).await);
+        assert_eq!(Ok(()), g.chat(cred_spec, "X is doomed".into()).await);
+        assert_eq!(Err(ChatError::UnknownSender), g.chat("nobody".into(), "hi".into()).await);
+        let inner = g.inner.lock().unwrap();
+        let players_view = inner.chat.backlog(true);
+        assert_eq!(1, players_view.len());
+        assert_eq!("Player X", players_view[0].name);
+        let spectators_view = inner.chat.backlog(false);
+        assert_eq!(2, spectators_view.len());
+        assert_eq!("Sam", spectators_view[1].name);
+    }
+
+    #[actix_web::test]
+    async fn reactions_are_counted_and_throttled() {
+        let g = Game::new();
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let _s3 = g.join(Profile::default(), None).await.unwrap();
+        let (cred_x, cred_spec) = {
+            let inner = g.inner.lock().unwrap();
+            (inner.players[0].as_ref().unwrap().credentials.clone(), inner.spectators[0].credentials.clone())
+        };
+        for _ in 0..REACTION_LIMIT {
+            assert_eq!(Ok(()), g.react(cred_x.clone(), Reaction::GG).await);
+        }
+        assert_eq!(Err(ChatError::RateLimited), g.react(cred_x, Reaction::GG).await);
+        assert_eq!(Ok(()), g.react(cred_spec, Reaction::Wow).await);
+        assert_eq!(Err(ChatError::UnknownSender), g.react("nobody".into(), Reaction::Wow).await);
+        let info = g.inner.lock().unwrap().gameinfo();
+        assert_eq!(REACTION_LIMIT, info.reactions.get(Reaction::GG));
+        assert_eq!(1, info.reactions.get(Reaction::Wow));
+    }
+
+    #[actix_web::test]
+    async fn reactions_are_kept_in_the_record() {
+        let g = Game::new();
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        let o = g.credentials_of(Player::O).unwrap();
+        assert_eq!(Ok(()), g.react(x.clone(), Reaction::GG).await);
+        assert!(g.addmove(4, x.clone()).await);
+        assert_eq!(Ok(()), g.react(o.clone(), Reaction::Wow).await);
+        // a rematch is part of the same game, its reactions add up
+        assert!(g.rematch(o.clone()).await);
+        assert_eq!(Ok(()), g.react(o, Reaction::GG).await);
+        let record = g.record().unwrap().to_string();
+        let record: Vec<&str> = record.lines().filter(|l| !l.starts_with("[Date") && !l.starts_with("[Seed")).collect();
+        insta::assert_snapshot!(record.join("\n"));
+    }
+
+    #[actix_web::test]
+    async fn bots_answer_moves() {
+        let g = Game::with_bot(Bot::Builtin(tictactoe::Difficulty::hardest()), Player::O);
+        assert!(g.is_dead().await);
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        assert!(!g.is_dead().await);
+        assert!(g.game_ok().await.is_ok());
+        let cred = g.inner.lock().unwrap().players[0].as_ref().unwrap().credentials.clone();
+        assert!(g.addmove(0, cred).await);
+        g.play_bots().await;
+        let board = g.inner.lock().unwrap().board.clone();
+        assert_eq!(Player::X, board.next_turn);
+        assert_eq!(2, board.show().iter().filter(|f| **f != tictactoe::Field::Empty).count());
+    }
+
+    #[actix_web::test]
+    async fn bots_can_be_chosen_in_the_settings() {
+        let g = Game::with_settings(GameSettings { bot: Some(Bot::Perfect), ..Default::default() });
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert!(g.addmove(0, x).await);
+        g.play_bots().await;
+        // the only move that does not lose
+        assert_eq!("X../.O./... x", g.board().unwrap().to_notation());
+    }
+
+    #[actix_web::test]
+    async fn seeded_bot_games_replay_exactly() {
+        let play = |seed| async move {
+            let settings = GameSettings {
+                bot: Some(Bot::Builtin(tictactoe::Difficulty::easiest())),
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let g = Game::with_settings(settings);
+            let _s1 = g.join(Profile::default(), None).await.unwrap();
+            let x = g.credentials_of(Player::X).unwrap();
+            while let Some(cell) = g.board().unwrap().legal_moves().first().copied() {
+                if !g.addmove(cell, x.clone()).await {
+                    break;
+                }
+                g.play_bots().await;
+            }
+            assert_eq!(Some(seed.to_string().as_str()), g.record().unwrap().header("Seed"));
+            g.board().unwrap().history().to_vec()
+        };
+        let replayed = play(11).await;
+        assert_eq!(replayed, play(11).await);
+        let mut others = Vec::new();
+        for seed in 12..16 {
+            others.push(play(seed).await);
+        }
+        assert!(others.iter().any(|h| *h != replayed));
+    }
+
+    #[actix_web::test]
+    async fn remote_bots_answer_with_their_move() {
+        let (url, _) = crate::bot::remote::tests::stub(vec![(200, r#"{"move": "b2"}"#)]);
+        let remote = RemoteBot::new("stub", &url, crate::bot::remote::tests::quick()).unwrap();
+        let g = Game::with_settings(GameSettings { bot: Some(Bot::Remote(Arc::new(remote))), ..Default::default() });
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert!(g.addmove(0, x).await);
+        g.play_bots().await;
+        assert_eq!("X../.O./... x", g.board().unwrap().to_notation());
+    }
+
+    #[actix_web::test]
+    async fn remote_bots_forfeit_on_invalid_answers() {
+        let (url, _) = crate::bot::remote::tests::stub(vec![(200, r#"{"move": "a1"}"#)]);
+        let remote = RemoteBot::new("stub", &url, crate::bot::remote::tests::quick()).unwrap();
+        let g = Game::with_settings(GameSettings { bot: Some(Bot::Remote(Arc::new(remote))), ..Default::default() });
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert!(g.addmove(0, x.clone()).await);
+        g.play_bots().await;
+        assert!(!g.addmove(4, x).await);
+        let record = g.record().unwrap();
+        assert!(record.header("Termination").is_some_and(|t| t.ends_with("forfeited")));
+        assert_eq!(Some(Player::X), g.take_result().unwrap().winner);
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn engines_think_without_holding_the_game() {
+        let script = "while read command rest; do case $command in \
+            tttp) echo tttpok;; isready) echo readyok;; go) sleep 0.3; echo 'bestmove b2';; quit) exit;; esac; done";
+        let engine = ExternalEngine::spawn("sh", &["-c".into(), script.into()], Duration::from_millis(500)).unwrap();
+        let g = Arc::new(Game::with_settings(GameSettings { bot: Some(Bot::External(Arc::new(engine))), ..Default::default() }));
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert!(g.addmove(0, x).await);
+        let thinking = actix_web::rt::spawn({
+            let g = Arc::clone(&g);
+            async move { g.play_bots().await }
+        });
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!("X../.../... o", g.board().unwrap().to_notation());
+        thinking.await.unwrap();
+        assert_eq!("X../.O./... x", g.board().unwrap().to_notation());
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn engines_stop_when_the_game_is_over() {
+        let script = "while read command rest; do case $command in \
+            tttp) echo tttpok;; isready) echo readyok;; go) echo 'bestmove b2';; quit) exit;; esac; done";
+        let engine = Arc::new(ExternalEngine::spawn("sh", &["-c".into(), script.into()], Duration::from_millis(500)).unwrap());
+        let g = Game::with_settings(GameSettings { bot: Some(Bot::External(Arc::clone(&engine))), ..Default::default() });
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert!(g.addmove(0, x.clone()).await);
+        g.play_bots().await;
+        assert!(engine.is_running());
+        assert!(g.forfeit(&x));
+        assert!(!engine.is_running());
+    }
+
+    #[actix_web::test]
+    async fn bots_open_when_they_play_x() {
+        let g = Game::with_bot(Bot::Builtin(tictactoe::Difficulty::hardest()), Player::X);
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let board = g.inner.lock().unwrap().board.clone();
+        assert_eq!(Player::O, board.next_turn);
+    }
+
+    #[actix_web::test]
+    async fn summary_counts_seats() {
+        let g = Game::with_settings(GameSettings { public: true, ..Default::default() });
+        assert!(g.is_public());
+        assert_eq!(GameStatus::Waiting, g.summary("foo").unwrap().status);
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let summary = g.summary("foo").unwrap();
+        assert_eq!((1, 1), (summary.seats_taken, summary.seats_free));
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let _s3 = g.join(Profile::default(), None).await.unwrap();
+        let summary = g.summary("foo").unwrap();
+        assert_eq!(GameStatus::Playing, summary.status);
+        assert_eq!(1, summary.spectators);
+        assert_eq!("/foo/game", summary.url);
+    }
+
+    #[actix_web::test]
+    async fn passphrases_guard_seats_and_spectating() {
+        let g = Game::with_settings(GameSettings {
+            passphrase: Some("open sesame".into()),
+            spectator_passphrase: Some("just looking".into()),
+            ..Default::default()
+        });
+        assert!(g.is_protected());
+        assert_eq!(Some(JoinError::WrongPassphrase), g.join(Profile::default(), None).await.err());
+        assert_eq!(Some(JoinError::WrongPassphrase), g.join(Profile::default(), Some("guess")).await.err());
+        let _s1 = g.join(Profile::default(), Some("open sesame")).await.unwrap();
+        let _s2 = g.join(Profile::default(), Some("just looking")).await.unwrap();
+        assert_eq!(Ok(false), g.healtchcheck().await);
+        assert_eq!(1, g.inner.lock().unwrap().spectators.len());
+        let _s3 = g.join(Profile::default(), Some("open sesame")).await.unwrap();
+        assert_eq!(Ok(true), g.healtchcheck().await);
+    }
+
+    #[actix_web::test]
+    async fn player_passphrase_alone_protects_spectating() {
+        let g = Game::with_settings(GameSettings {
+            passphrase: Some("open sesame".into()),
+            ..Default::default()
+        });
+        let _s1 = g.join(Profile::default(), Some("open sesame")).await.unwrap();
+        let _s2 = g.join(Profile::default(), Some("open sesame")).await.unwrap();
+        assert!(g.join(Profile::default(), None).await.is_err());
+        let _s3 = g.join(Profile::default(), Some("open sesame")).await.unwrap();
+        assert_eq!(1, g.inner.lock().unwrap().spectators.len());
+    }
+
+    #[actix_web::test]
+    async fn seat_keys_reserve_one_seat_each() {
+        let g = Game::with_settings(GameSettings {
+            seat_keys: Some(["alice".into(), "bob".into()]),
+            ..Default::default()
+        });
+        let _s1 = g.join(Profile::default(), Some("alice")).await.unwrap();
+        let _s2 = g.join(Profile::default(), Some("alice")).await.unwrap();
+        let _s3 = g.join(Profile::default(), None).await.unwrap();
+        assert_eq!(Ok(false), g.healtchcheck().await);
+        assert_eq!(2, g.inner.lock().unwrap().spectators.len());
+        let _s4 = g.join(Profile::default(), Some("bob")).await.unwrap();
+        assert_eq!(Ok(true), g.healtchcheck().await);
+        let bob = g.credentials_of(Player::O).unwrap();
+        let alice = g.credentials_of(Player::X).unwrap();
+        for (i, field) in [0, 3, 1, 4, 2].into_iter().enumerate() {
+            assert!(g.addmove(field, [&alice, &bob][i % 2].clone()).await);
+        }
+        let result = g.take_result().unwrap();
+        assert_eq!([Some("alice".to_string()), Some("bob".to_string())], result.seat_keys);
+    }
+
+    #[actix_web::test]
+    async fn records_name_players_and_moves() {
+        let g = Game::new();
+        let _s1 = g.join(Profile::new(Some("alice"), None), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert!(g.addmove(4, x).await);
+        let record = g.record().unwrap();
+        assert_eq!(Some("alice"), record.header("X"));
+        assert_eq!(Some("Player O"), record.header("O"));
+        assert_eq!(Some("*"), record.header("Result"));
+        assert_eq!(vec![4], record.moves);
+        assert_eq!(record, record.to_string().parse().unwrap());
+    }
+
+    #[actix_web::test]
+    async fn games_start_from_their_position() {
+        let position = Board::from_notation("XO./.X./... o").unwrap();
+        let g = Game::with_settings(GameSettings { position: Some(position), ..Default::default() });
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        assert!(g.addmove(8, g.credentials_of(Player::O).unwrap()).await);
+        assert_eq!(Some("XO./.X./... o"), g.record().unwrap().header("Position"));
+        let cred = g.credentials_of(Player::X).unwrap();
+        assert!(g.rematch(cred).await);
+        assert_eq!("XO./.X./... o", g.inner.lock().unwrap().board.to_notation());
+    }
+
+    #[actix_web::test]
+    async fn hints_have_a_budget() {
+        let g = Game::with_settings(GameSettings { hints: 1, ..Default::default() });
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        let o = g.credentials_of(Player::O).unwrap();
+        assert_eq!(Err(HintError::NotYourTurn), g.hint(&o));
+        assert_eq!(Err(HintError::UnknownPlayer), g.hint("nobody"));
+        assert!(g.addmove(0, x.clone()).await);
+        // the center is the only answer to a corner that does not lose
+        let hint = g.hint(&o).unwrap();
+        assert_eq!((4, 0), (hint.cell, hint.remaining));
+        assert!(g.addmove(1, o.clone()).await);
+        assert!(g.addmove(3, x.clone()).await);
+        assert_eq!(Err(HintError::BudgetSpent), g.hint(&o));
+        for (cred, field) in [(&o, 2), (&x, 6)] {
+            assert!(g.addmove(field, cred.clone()).await);
+        }
+        assert_eq!([0, 1], g.take_result().unwrap().hints_used);
+    }
+
+    #[actix_web::test]
+    async fn reconnecting_keeps_the_hint_budget() {
+        let g = Game::with_settings(GameSettings { hints: 1, ..Default::default() });
+        let s1 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert_eq!(Err(HintError::NotStarted), g.hint(&x));
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        assert!(g.hint(&x).is_ok());
+        drop(s1);
+        let _s3 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert_eq!(Err(HintError::BudgetSpent), g.hint(&x));
+    }
+
+    #[actix_web::test]
+    async fn hints_can_be_disabled() {
+        let g = Game::new();
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let x = g.credentials_of(Player::X).unwrap();
+        assert_eq!(Err(HintError::Disabled), g.hint(&x));
+    }
+
+    #[actix_web::test]
+    async fn results_are_reported_once() {
+        let g = Game::with_settings(GameSettings { rated: true, ..Default::default() });
+        let _s1 = g.join(Profile::default().with_user(1, "alice"), None).await.unwrap();
+        let _s2 = g.join(Profile::default().with_user(2, "bob"), None).await.unwrap();
+        let players = g.game_ok().await.unwrap();
+        assert!(g.take_result().is_none());
+        for (i, field) in [0, 3, 1, 4, 2].into_iter().enumerate() {
+            assert!(g.addmove(field, players[i % 2].credentials.clone()).await);
+        }
+        let result = g.take_result().unwrap();
+        assert_eq!(Some(Player::X), result.winner);
+        assert_eq!(Some((1, 2)), result.rated_accounts());
+        assert_eq!(1.0, result.score_x());
+        assert!(g.take_result().is_none());
+    }
+
+    #[actix_web::test]
+    async fn only_finished_boards_are_given_out() {
+        let g = Game::new();
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let players = g.game_ok().await.unwrap();
+        assert!(g.addmove(4, players[0].credentials.clone()).await);
+        assert!(g.finished_board().is_none());
+        assert!(g.forfeit(&players[1].credentials));
+        assert_eq!(1, g.finished_board().unwrap().history().len());
+    }
+
+    #[actix_web::test]
+    async fn bot_games_are_not_rated() {
+        let g = Game::with_settings(GameSettings { rated: true, ..Default::default() });
+        g.inner.lock().unwrap().players[1] = Some(ActivePlayer::bot(Bot::Builtin(tictactoe::Difficulty::hardest())));
+        let _s1 = g.join(Profile::default().with_user(1, "alice"), None).await.unwrap();
+        let cred = g.inner.lock().unwrap().players[0].as_ref().unwrap().credentials.clone();
+        while g.inner.lock().unwrap().board.get_winner().is_none() {
+            let field = g.inner.lock().unwrap().board.show().iter().position(|f| *f == tictactoe::Field::Empty).unwrap();
+            assert!(g.addmove(field, cred.clone()).await);
+            g.play_bots().await;
+        }
+        let result = g.take_result().unwrap();
+        assert!(result.against_bot);
+        assert_eq!(None, result.rated_accounts());
+    }
+
     #[actix_web::test]
     async fn rejoin_when_player_drops() {
         let g = Game::new();
-        let s1 = g.join().await;
+        let s1 = g.join(Profile::default(), None).await.unwrap();
         assert_eq!(Ok(false), g.healtchcheck().await);
-        let s2 = g.join().await;
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
         assert!(g.game_ok().await.is_ok());
         drop(s1);
         assert_eq!(Ok(false), g.healtchcheck().await);
-        let s3 = g.join().await;
+        let _s3 = g.join(Profile::default(), None).await.unwrap();
         assert!(g.game_ok().await.is_ok());
     }
+
+    /// The ids and messages of the events waiting on the stream
+    async fn received(stream: &mut sse::Sse<ChannelStream>) -> Vec<(u64, ServerMessage)> {
+        let text = std::future::poll_fn(|cx| {
+            let mut text = String::new();
+            while let std::task::Poll::Ready(Some(Ok(chunk))) = std::pin::Pin::new(&mut *stream).poll_next(cx) {
+                text.push_str(&String::from_utf8_lossy(&chunk));
+            }
+            std::task::Poll::Ready(text)
+        }).await;
+        let mut events = Vec::new();
+        let mut id = 0;
+        for line in text.lines() {
+            if let Some(value) = line.strip_prefix("id: ") {
+                id = value.parse().unwrap();
+            } else if let Some(data) = line.strip_prefix("data: ") {
+                events.push((id, ServerMessage::parse(data).unwrap().message));
+            }
+        }
+        events
+    }
+
+    #[actix_web::test]
+    async fn reconnecting_players_get_what_they_missed() {
+        let g = Game::new();
+        let alice = || Profile::default().with_user(1, "alice");
+        let mut s1 = g.join(alice(), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        let seen = received(&mut s1).await;
+        let ids: Vec<u64> = seen.iter().map(|(id, _)| *id).collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        let last_seen = *ids.last().unwrap();
+        let cred_x = g.inner.lock().unwrap().players[0].as_ref().unwrap().credentials.clone();
+        assert!(g.addmove(4, cred_x).await);
+        g.show().await;
+        drop(s1);
+        let mut s3 = g.rejoin(alice(), None, Some(last_seen)).await.unwrap();
+        let missed = received(&mut s3).await;
+        assert!(missed.iter().all(|(id, _)| *id > last_seen));
+        assert!(missed.windows(2).all(|w| w[0].0 < w[1].0));
+        match &missed[0].1 {
+            ServerMessage::State(info) => assert_eq!(tictactoe::Field::X, info.gamestate[4]),
+            other => panic!("expected the board, got {other:?}"),
+        }
+        assert_eq!(ServerMessage::Notification("Wait for your opponent".into()), missed[1].1);
+        assert!(matches!(missed[2].1, ServerMessage::Credentials(_)));
+    }
+
+    #[actix_web::test]
+    async fn new_players_do_not_get_what_was_meant_for_the_last_one() {
+        let g = Game::new();
+        let s1 = g.join(Profile::default().with_user(1, "alice"), None).await.unwrap();
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
+        drop(s1);
+        let mut s3 = g.rejoin(Profile::default().with_user(2, "mallory"), None, Some(0)).await.unwrap();
+        let missed = received(&mut s3).await;
+        assert!(!missed.is_empty());
+        assert!(!missed.iter().any(|(_, m)| matches!(m, ServerMessage::Notification(n) if n.contains("first move"))));
+    }
+
+    #[actix_web::test]
+    async fn reconnecting_too_late_still_shows_the_board() {
+        let g = Game::new();
+        let s1 = g.join(Profile::default(), None).await.unwrap();
+        let s2 = g.join(Profile::default(), None).await.unwrap();
+        // nobody reads along, the events would fill the streams
+        drop((s1, s2));
+        for _ in 0..REPLAY_LEN {
+            g.show().await;
+        }
+        let mut s3 = g.rejoin(Profile::default(), None, Some(1)).await.unwrap();
+        let events = received(&mut s3).await;
+        assert!(matches!(events[0].1, ServerMessage::Credentials(_)));
+        assert!(matches!(events.last().unwrap().1, ServerMessage::State(_)));
+    }
     #[actix_web::test]
     async fn spectators_can_just_drop() {
         let g = Game::new();
-        let s1 = g.join().await;
+        let _s1 = g.join(Profile::default(), None).await.unwrap();
         assert_eq!(Ok(false), g.healtchcheck().await);
-        let s2 = g.join().await;
+        let _s2 = g.join(Profile::default(), None).await.unwrap();
         assert!(g.game_ok().await.is_ok());
         assert_eq!(0, g.inner.lock().unwrap().spectators.len());
-        let s3 = g.join().await;
+        let s3 = g.join(Profile::default(), None).await.unwrap();
         assert!(g.game_ok().await.is_ok());
         assert_eq!(1, g.inner.lock().unwrap().spectators.len());
         drop(s3);
@@ -385,8 +1652,7 @@ mod tests {
     #[actix_web::test]
     async fn sse_assumptions() {
         let (tx, rx) = sse::channel(5);
-        let mut specs: Vec<sse::Sender> = Vec::new();
-        specs.push(tx.clone());
+        let specs: Vec<sse::Sender> = vec![tx.clone()];
         let m = specs[0].send(sse::Event::Comment("ping".into())).await;
         assert!(m.is_ok());
         let testreq = test::TestRequest::default().to_http_request();
@@ -396,7 +1662,7 @@ mod tests {
         //drop(rx);
         assert_eq!(BodySize::Stream, resp.body().size());
         
-        let m = specs[0].send(sse::Event::Comment("ping".into())).await;
+        let _m = specs[0].send(sse::Event::Comment("ping".into())).await;
 
         //assert!(m.is_err());
     }
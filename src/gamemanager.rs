@@ -1,23 +1,58 @@
-use crate::game::Game;
+use crate::accounts::{Accounts, UserId};
+use crate::bot::engine::{EngineError, ExternalEngine};
+use crate::bot::remote::{self, RemoteBot, RemoteError, RetryPolicy};
+use crate::bot::Bot;
+use crate::game::{Game, GameResult, GameSettings, GameSummary, Reporter};
+use crate::protocol::ServerMessage;
+use crate::rating::INITIAL_RATING;
+use crate::tictactoe::{Difficulty, Player};
+use crate::tournament::{Format, NewPairing, Standings, Status, Tournament, TournamentError};
 /// Types and methods related to managing many games
 use actix_web_lab::sse::{self, ChannelStream};
-use log::logger;
-use serde::Serialize;
-use serde_json;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
-use std::time::Duration;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+/// How long a player waits in the matchmaking queue before getting a bot opponent
+pub const MATCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long entrants have to take their seats before their game goes to whoever showed up
+pub const TOURNAMENT_JOIN_TIMEOUT: Duration = Duration::from_secs(600);
+/// Tournaments are forgotten this long after their last change
+pub const TOURNAMENT_TTL: Duration = Duration::from_secs(3600);
+/// How far apart ratings may be for two players to be matched right away
+pub const RATING_WINDOW: f64 = 200.0;
+/// How much the window widens for every second a player has been waiting
+pub const RATING_WINDOW_GROWTH: f64 = 20.0;
 
 pub struct GameManager {
     inner: RwLock<GameManagerInner>,
+    match_timeout: Duration,
+    accounts: Option<Arc<Accounts>>,
+    /// The manager itself, for games to hand their results to
+    this: Weak<GameManager>,
 }
 impl GameManager {
-    /// start the gamemanager, launch ping
+    /// start the gamemanager without accounts, launch ping
     pub fn init() -> Arc<Self> {
-        let this = Arc::new(GameManager {
+        GameManager::init_with_accounts(None)
+    }
+    /// start the gamemanager, launch ping
+    pub fn init_with_accounts(accounts: Option<Accounts>) -> Arc<Self> {
+        let this = Arc::new_cyclic(|this| GameManager {
+            this: this.clone(),
+            accounts: accounts.map(Arc::new),
             inner: RwLock::new(GameManagerInner {
                 games: HashMap::new(),
+                queue: VecDeque::new(),
+                lobby_subscribers: Vec::new(),
+                tournaments: HashMap::new(),
+                tournament_games: HashMap::new(),
+                remote_bots: HashMap::new(),
+                engines: HashMap::new(),
             }),
+            match_timeout: MATCH_TIMEOUT,
         });
         GameManager::start_ping(Arc::clone(&this));
         this
@@ -30,7 +65,12 @@ impl GameManager {
             loop {
                 interval.tick().await;
                 log::info!("Cleanup cycle starts");
-                this.remove_dead_games().await;
+                if let Err(e) = this.remove_dead_games().await {
+                    log::error!("Cleanup failed: {e}");
+                }
+                this.forget_unused_remote_bots();
+                this.forfeit_no_shows(TOURNAMENT_JOIN_TIMEOUT);
+                this.forget_idle_tournaments(TOURNAMENT_TTL);
                 log::info!("Finished cleanup cycle.");
             }
         });
@@ -38,11 +78,83 @@ impl GameManager {
 }
 struct GameManagerInner {
     games: HashMap<String, Arc<Game>>,
+    queue: VecDeque<Waiting>,
+    lobby_subscribers: Vec<sse::Sender>,
+    tournaments: HashMap<String, TournamentEntry>,
+    /// Which tournament a game belongs to, kept until the tournament is over
+    tournament_games: HashMap<String, String>,
+    /// Bots behind HTTP callbacks, by the id they were registered under
+    remote_bots: HashMap<String, RemoteEntry>,
+    /// Engines started for each account, to keep count of the ones running
+    engines: HashMap<UserId, Vec<Weak<ExternalEngine>>>,
+}
+/// A remote bot along with who registered it and when a game last picked it
+struct RemoteEntry {
+    bot: Arc<RemoteBot>,
+    owner: UserId,
+    last_used: Instant,
+}
+/// A tournament along with who runs it and who follows it
+struct TournamentEntry {
+    tournament: Tournament,
+    organizer_key: String,
+    subscribers: Vec<sse::Sender>,
+    /// When entrants, pairings or results last changed
+    changed: Instant,
+}
+/// A player in the matchmaking queue
+struct Waiting {
+    ticket: String,
+    stream: sse::Sender,
+    rating: f64,
+    since: Instant,
+}
+impl Waiting {
+    /// Whether someone with `rating` is close enough to be matched with this player now
+    fn accepts(&self, rating: f64) -> bool {
+        let window = RATING_WINDOW + RATING_WINDOW_GROWTH * self.since.elapsed().as_secs_f64();
+        (self.rating - rating).abs() <= window
+    }
+}
+/// Sent to players in the matchmaking queue once their game is ready
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Match {
+    pub gameid: String,
+    pub url: String,
+    /// No opponent was found in time, a bot plays instead
+    pub bot: bool,
+}
+impl Match {
+    pub(crate) fn new(gameid: String, bot: bool) -> Self {
+        Match {
+            url: format!("/{gameid}/game"),
+            gameid,
+            bot,
+        }
+    }
+    async fn send(&self, stream: &sse::Sender) -> bool {
+        stream.send(ServerMessage::Matched(self.clone())).await.is_ok()
+    }
 }
 impl GameManager {
+    /// The account database, if the server runs with one
+    pub fn accounts(&self) -> Option<Arc<Accounts>> {
+        self.accounts.clone()
+    }
     async fn remove_dead_games(&self) -> Result<(), Box<dyn std::error::Error + '_>> {
         let mut deadgames: Vec<String> = Vec::new();
-        for (key, game) in &self.inner.read()?.games {
+        let games: Vec<(String, Arc<Game>)> = self
+            .inner
+            .read()?
+            .games
+            .iter()
+            .map(|(key, game)| (key.clone(), Arc::clone(game)))
+            .collect();
+        for (key, game) in games {
+            // tournament games wait for their players however long it takes
+            if self.inner.read()?.tournament_games.contains_key(&key) {
+                continue;
+            }
             if game.is_dead().await {
                 deadgames.push(key.to_string());
                 log::info!("Found dead game, scheduling for removal: {}", key);
@@ -52,12 +164,29 @@ impl GameManager {
             self.inner.write()?.games.remove(&key);
             log::info!("Removed game: {}", key);
         }
+        self.notify_lobby();
         Ok(())
     }
     /// Create a new game with a given ID
     /// Will overwrite if a game with the same ID already exists (use uuid)
-    pub fn newgame(&self, id: String) -> Result<(), Box<dyn std::error::Error + '_>> {
-        match self.inner.write()?.games.insert(id.clone(), Game::new()) {
+    pub fn newgame(&self, id: String, settings: GameSettings) -> Result<(), Box<dyn std::error::Error + '_>> {
+        self.insert_game(id, Game::with_settings(settings))
+    }
+    fn insert_game(&self, id: String, game: Arc<Game>) -> Result<(), Box<dyn std::error::Error + '_>> {
+        let this = self.this.clone();
+        let gameid = id.clone();
+        game.report_to(Reporter::new(move |result| {
+            if let Some(gm) = this.upgrade() {
+                let gameid = gameid.clone();
+                actix_web::rt::spawn(async move { gm.report(&gameid, result).await });
+            }
+        }));
+        let public = game.is_public();
+        let previous = self.inner.write()?.games.insert(id.clone(), game);
+        if public {
+            self.notify_lobby();
+        }
+        match previous {
             None => Ok(()),
             Some(g) => {
                 log::error!(
@@ -84,34 +213,674 @@ impl GameManager {
             },
         }
     }
+    /// Public games that wait for an opponent or can be watched, newest first
+    pub fn lobby(&self) -> Vec<GameSummary> {
+        let mut games: Vec<GameSummary> = match self.inner.read() {
+            Ok(guard) => guard
+                .games
+                .iter()
+                .filter(|(_, game)| game.is_public())
+                .filter_map(|(id, game)| game.summary(id))
+                .collect(),
+            Err(e) => {
+                log::error!("Error getting ReaderLock! {:?}", e);
+                Vec::new()
+            }
+        };
+        games.sort_by_key(|g| g.age);
+        games
+    }
+    /// Subscribe to the lobby, the current listing is sent right away and again on every change
+    pub async fn lobby_events(&self) -> sse::Sse<ChannelStream> {
+        let (tx, rx) = sse::channel(10);
+        let _ = tx.send(ServerMessage::Lobby(self.lobby())).await;
+        if let Ok(mut guard) = self.inner.write() {
+            guard.lobby_subscribers.push(tx);
+        }
+        rx
+    }
+    /// Push the current listing to lobby subscribers, forgetting those that went away
+    /// Never waits, subscribers that are behind simply miss an update
+    pub fn notify_lobby(&self) {
+        let lobby = sse::Data::from(ServerMessage::Lobby(self.lobby()));
+        if let Ok(mut guard) = self.inner.write() {
+            guard.lobby_subscribers.retain(|sub| {
+                !matches!(
+                    sub.try_send(lobby.clone()),
+                    Err(sse::TrySendError::Closed(_))
+                )
+            });
+        }
+    }
+    /// Hand the result of a finished game to everything that keeps track of results,
+    /// games of the manager do so on their own as soon as a board is decided
+    async fn report(&self, gameid: &str, result: GameResult) {
+        if let (Some((x, o)), Some(accounts)) = (result.rated_accounts(), self.accounts()) {
+            let score = result.score_x();
+            match actix_web::web::block(move || accounts.record_game(x, o, score)).await {
+                Ok(Ok((x_rating, o_rating))) => log::info!(
+                    "Rated game recorded, new ratings {:.0} and {:.0}",
+                    x_rating.rating,
+                    o_rating.rating
+                ),
+                Ok(Err(e)) => log::error!("Could not record rated game: {e}"),
+                Err(e) => log::error!("Could not record rated game: {e}"),
+            }
+        }
+        self.record_tournament_game(gameid, &result);
+    }
+    /// Pair the player with whoever is waiting in the matchmaking queue, or queue them up
+    /// Only players within the rating window are paired, the closest rating first, anonymous
+    /// players count as newcomers. Both players get a "matched" event pointing to their new game,
+    /// if nobody fitting shows up within the timeout the player gets a game against a bot
+    pub async fn matchmake(this: Arc<Self>, rating: Option<f64>) -> sse::Sse<ChannelStream> {
+        let rating = rating.unwrap_or(INITIAL_RATING);
+        let (tx, rx) = sse::channel(10);
+        loop {
+            let waiting = match this.inner.write() {
+                Ok(mut guard) => {
+                    let closest = guard
+                        .queue
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, w)| w.accepts(rating))
+                        .min_by(|(_, a), (_, b)| {
+                            (a.rating - rating).abs().total_cmp(&(b.rating - rating).abs())
+                        })
+                        .map(|(i, _)| i);
+                    closest.and_then(|i| guard.queue.remove(i))
+                }
+                Err(e) => {
+                    log::error!("Error getting WriterLock! {:?}", e);
+                    return rx;
+                }
+            };
+            let waiting = match waiting {
+                Some(w) => w,
+                None => break,
+            };
+            // players who left the queue are only noticed now
+            if waiting.stream.send(sse::Event::Comment("ping".into())).await.is_err() {
+                continue;
+            }
+            let gameid = nanoid!(8);
+            let settings = GameSettings {
+                rated: true,
+                ..Default::default()
+            };
+            if this.newgame(gameid.clone(), settings).is_err() {
+                return rx;
+            }
+            log::info!("Matched two players into game {gameid}");
+            let found = Match::new(gameid, false);
+            found.send(&waiting.stream).await;
+            found.send(&tx).await;
+            return rx;
+        }
+
+        let ticket = nanoid!(12);
+        let _ = tx.send(ServerMessage::Notification("Waiting for an opponent...".into())).await;
+        match this.inner.write() {
+            Ok(mut guard) => guard.queue.push_back(Waiting {
+                ticket: ticket.clone(),
+                stream: tx,
+                rating,
+                since: Instant::now(),
+            }),
+            Err(e) => {
+                log::error!("Error getting WriterLock! {:?}", e);
+                return rx;
+            }
+        }
+        let timeout = this.match_timeout;
+        actix_web::rt::spawn(async move {
+            actix_web::rt::time::sleep(timeout).await;
+            this.match_with_bot(ticket).await;
+        });
+        rx
+    }
+    /// Give a player who is still waiting after the timeout a game against a bot
+    async fn match_with_bot(&self, ticket: String) {
+        let waiting = match self.inner.write() {
+            Ok(mut guard) => {
+                let pos = guard.queue.iter().position(|w| w.ticket == ticket);
+                pos.and_then(|pos| guard.queue.remove(pos))
+            }
+            Err(_) => None,
+        };
+        let waiting = match waiting {
+            Some(w) => w,
+            None => {
+                return;
+            }
+        };
+        let gameid = nanoid!(8);
+        let game = Game::with_bot(Bot::Builtin(Difficulty::hardest()), Player::O);
+        if self.insert_game(gameid.clone(), game).is_err() {
+            return;
+        }
+        log::info!("Nobody showed up, matched player with a bot in game {gameid}");
+        Match::new(gameid, true).send(&waiting.stream).await;
+    }
+}
+
+/// How many remote bots may be registered
+pub const MAX_REMOTE_BOTS: usize = 100;
+/// How many remote bots one account may have registered
+pub const MAX_REMOTE_BOTS_PER_USER: usize = 5;
+/// Remote bots that no game picked for this long are forgotten once no game plays them
+pub const REMOTE_BOT_TTL: Duration = Duration::from_secs(3600);
+
+impl GameManager {
+    /// Register a bot behind an HTTP callback for an account, returns the id games pick it with
+    pub fn register_remote_bot(&self, owner: UserId, name: &str, url: &str) -> Result<String, RemoteError> {
+        let bot = RemoteBot::new(name, url, RetryPolicy::default())?;
+        if !remote::port_allowed(url) {
+            return Err(RemoteError::PortNotAllowed(url.into()));
+        }
+        let mut guard = self.inner.write().map_err(|_| RemoteError::Full)?;
+        forget_unused(&mut guard.remote_bots);
+        let owned = guard.remote_bots.values().filter(|e| e.owner == owner).count();
+        if guard.remote_bots.len() >= MAX_REMOTE_BOTS || owned >= MAX_REMOTE_BOTS_PER_USER {
+            return Err(RemoteError::Full);
+        }
+        let id = nanoid!(8);
+        let entry = RemoteEntry { bot: Arc::new(bot), owner, last_used: Instant::now() };
+        guard.remote_bots.insert(id.clone(), entry);
+        Ok(id)
+    }
+    /// The remote bot registered under this id, which counts as using it
+    pub fn remote_bot(&self, id: &str) -> Option<Arc<RemoteBot>> {
+        let mut guard = self.inner.write().ok()?;
+        let entry = guard.remote_bots.get_mut(id)?;
+        entry.last_used = Instant::now();
+        Some(Arc::clone(&entry.bot))
+    }
+    /// Forget remote bots that went unused for `REMOTE_BOT_TTL`
+    fn forget_unused_remote_bots(&self) {
+        if let Ok(mut guard) = self.inner.write() {
+            forget_unused(&mut guard.remote_bots);
+        }
+    }
+}
+/// Drops the bots no game picked within `REMOTE_BOT_TTL` and no game still plays
+fn forget_unused(bots: &mut HashMap<String, RemoteEntry>) {
+    bots.retain(|_, e| e.last_used.elapsed() < REMOTE_BOT_TTL || Arc::strong_count(&e.bot) > 1);
+}
+
+/// How many engines one account may have running at once
+pub const MAX_ENGINES_PER_USER: usize = 2;
+
+impl GameManager {
+    /// Keep count of an engine started for an account, which is stopped right away if the
+    /// account has `MAX_ENGINES_PER_USER` running already
+    pub fn adopt_engine(&self, owner: UserId, engine: ExternalEngine) -> Result<Arc<ExternalEngine>, EngineError> {
+        let mut guard = self.inner.write().map_err(|_| EngineError::Busy)?;
+        // engines of finished games are stopped, and gone once their game is
+        guard.engines.retain(|_, engines| {
+            engines.retain(|e| e.upgrade().is_some_and(|e| e.is_running()));
+            !engines.is_empty()
+        });
+        let engines = guard.engines.entry(owner).or_default();
+        if engines.len() >= MAX_ENGINES_PER_USER {
+            return Err(EngineError::Busy);
+        }
+        let engine = Arc::new(engine);
+        engines.push(Arc::downgrade(&engine));
+        Ok(engine)
+    }
+}
+
+/// Returned to whoever creates a tournament, the key is needed to start it
+#[derive(Debug, Serialize)]
+pub struct NewTournament {
+    pub id: String,
+    pub url: String,
+    pub organizer_key: String,
+}
+impl GameManager {
+    /// Open a tournament for registration
+    pub fn new_tournament(&self, name: &str, format: Format) -> Result<NewTournament, TournamentError> {
+        let tournament = Tournament::new(name, format)?;
+        let id = nanoid!(8);
+        let organizer_key = nanoid!(12);
+        let mut guard = self.inner.write().map_err(|_| TournamentError::NotFound)?;
+        guard.tournaments.insert(
+            id.clone(),
+            TournamentEntry {
+                tournament,
+                organizer_key: organizer_key.clone(),
+                subscribers: Vec::new(),
+                changed: Instant::now(),
+            },
+        );
+        Ok(NewTournament {
+            url: format!("/tournaments/{id}"),
+            id,
+            organizer_key,
+        })
+    }
+    /// Register an entrant, returns the key that gets them into their games
+    pub fn enter_tournament(&self, id: &str, name: &str) -> Result<String, TournamentError> {
+        let key = self.with_tournament(id, |entry| {
+            entry.changed = Instant::now();
+            entry.tournament.register(name)
+        })?;
+        self.notify_tournament(id);
+        Ok(key)
+    }
+    /// Close registration and create the games of the first pairings
+    pub fn start_tournament(&self, id: &str, organizer_key: &str) -> Result<(), TournamentError> {
+        let pairings = self.with_tournament(id, |entry| {
+            if entry.organizer_key != organizer_key {
+                return Err(TournamentError::NotOrganizer);
+            }
+            entry.changed = Instant::now();
+            entry.tournament.start()
+        })?;
+        log::info!("Tournament {id} started");
+        self.create_tournament_games(id, pairings);
+        Ok(())
+    }
+    pub fn standings(&self, id: &str) -> Result<Standings, TournamentError> {
+        self.with_tournament(id, |entry| Ok(entry.tournament.standings()))
+    }
+    /// Subscribe to a tournament, the standings are sent right away and again on every change
+    pub async fn tournament_events(&self, id: &str) -> Result<sse::Sse<ChannelStream>, TournamentError> {
+        let standings = ServerMessage::Standings(self.standings(id)?);
+        let (tx, rx) = sse::channel(10);
+        let _ = tx.send(standings).await;
+        self.with_tournament(id, |entry| {
+            entry.subscribers.push(tx);
+            Ok(())
+        })?;
+        Ok(rx)
+    }
+    fn with_tournament<T>(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut TournamentEntry) -> Result<T, TournamentError>,
+    ) -> Result<T, TournamentError> {
+        let mut guard = self.inner.write().map_err(|_| TournamentError::NotFound)?;
+        f(guard.tournaments.get_mut(id).ok_or(TournamentError::NotFound)?)
+    }
+    /// Every pairing gets a game whose seats are reserved for its two entrants
+    fn create_tournament_games(&self, id: &str, pairings: Vec<NewPairing>) {
+        for pairing in pairings {
+            let gameid = nanoid!(8);
+            let settings = GameSettings {
+                seat_keys: Some(pairing.keys),
+                ..Default::default()
+            };
+            if self.newgame(gameid.clone(), settings).is_err() {
+                continue;
+            }
+            if let Ok(mut guard) = self.inner.write() {
+                guard.tournament_games.insert(gameid.clone(), id.to_string());
+                if let Some(entry) = guard.tournaments.get_mut(id) {
+                    entry.tournament.assign_game(pairing.round, pairing.index, gameid);
+                }
+            }
+        }
+        self.notify_tournament(id);
+    }
+    /// Advance the tournament a finished game belongs to, if any
+    fn record_tournament_game(&self, gameid: &str, result: &GameResult) {
+        let id = match self.inner.read() {
+            Ok(guard) => guard.tournament_games.get(gameid).cloned(),
+            Err(_) => None,
+        };
+        let id = match id {
+            Some(id) => id,
+            None => return,
+        };
+        let keys = [result.seat_keys[0].as_deref(), result.seat_keys[1].as_deref()];
+        let winner = result.winner.map(|p| p as usize);
+        self.advance_tournament(&id, |t| t.record(gameid, keys, winner));
+    }
+    /// Change a tournament through `f`, then create the games of the pairings it returns
+    fn advance_tournament(&self, id: &str, f: impl FnOnce(&mut Tournament) -> Vec<NewPairing>) {
+        let recorded = self.with_tournament(id, |entry| {
+            entry.changed = Instant::now();
+            let pairings = f(&mut entry.tournament);
+            Ok((pairings, entry.tournament.status()))
+        });
+        let (pairings, status) = match recorded {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        if status == Status::Finished {
+            log::info!("Tournament {id} is over");
+            if let Ok(mut guard) = self.inner.write() {
+                guard.tournament_games.retain(|_, t| t != id);
+            }
+        }
+        self.create_tournament_games(id, pairings);
+    }
+    /// Give tournament games that their entrants did not join within `timeout` to whoever
+    /// showed up, so one missing entrant cannot hold up the tournament
+    fn forfeit_no_shows(&self, timeout: Duration) {
+        let games: Vec<(String, String, Arc<Game>)> = match self.inner.read() {
+            Ok(guard) => guard
+                .tournament_games
+                .iter()
+                .filter_map(|(gameid, id)| {
+                    let game = guard.games.get(gameid)?;
+                    Some((gameid.clone(), id.clone(), Arc::clone(game)))
+                })
+                .collect(),
+            Err(_) => return,
+        };
+        for (gameid, id, game) in games {
+            if game.age() < timeout {
+                continue;
+            }
+            let seated = match game.seated_keys() {
+                Some(seated) => seated,
+                None => continue,
+            };
+            log::info!("Entrants did not show up for game {gameid} of tournament {id}");
+            if let Ok(mut guard) = self.inner.write() {
+                guard.tournament_games.remove(&gameid);
+                guard.games.remove(&gameid);
+            }
+            self.advance_tournament(&id, |t| t.no_show(&gameid, &seated));
+        }
+    }
+    /// Forget tournaments that did not change for `ttl`, along with their games
+    fn forget_idle_tournaments(&self, ttl: Duration) {
+        if let Ok(mut guard) = self.inner.write() {
+            let guard = &mut *guard;
+            guard.tournaments.retain(|id, entry| {
+                let keep = entry.changed.elapsed() < ttl;
+                if !keep {
+                    log::info!("Forgetting tournament {id}");
+                }
+                keep
+            });
+            let tournaments = &guard.tournaments;
+            let games = &mut guard.games;
+            guard.tournament_games.retain(|gameid, id| {
+                let keep = tournaments.contains_key(id);
+                if !keep {
+                    games.remove(gameid);
+                }
+                keep
+            });
+        }
+    }
+    /// Push the standings to subscribers, forgetting those that went away
+    fn notify_tournament(&self, id: &str) {
+        let standings = match self.standings(id) {
+            Ok(s) => sse::Data::from(ServerMessage::Standings(s)),
+            Err(_) => return,
+        };
+        let _ = self.with_tournament(id, |entry| {
+            entry.subscribers.retain(|sub| {
+                !matches!(
+                    sub.try_send(standings.clone()),
+                    Err(sse::TrySendError::Closed(_))
+                )
+            });
+            Ok(())
+        });
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::profile::Profile;
+    use crate::tictactoe::Player;
+
+    /// Waits for results, which games report in the background
+    async fn eventually(check: impl Fn() -> bool) {
+        for _ in 0..100 {
+            if check() {
+                return;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("never happened");
+    }
     #[actix_web::test]
     async fn can_create_game() {
         let gm = GameManager::init();
-        gm.newgame("foo".into());
+        let _ = gm.newgame("foo".into(), GameSettings::default());
         assert!(gm.getgame("foo".into()).is_some());
     }
+    #[actix_web::test]
+    async fn matchmaking_pairs_waiting_players() {
+        let gm = GameManager::init();
+        let _p1 = GameManager::matchmake(Arc::clone(&gm), None).await;
+        assert_eq!(1, gm.inner.read().unwrap().queue.len());
+        assert!(gm.inner.read().unwrap().games.is_empty());
+        let _p2 = GameManager::matchmake(Arc::clone(&gm), None).await;
+        assert!(gm.inner.read().unwrap().queue.is_empty());
+        assert_eq!(1, gm.inner.read().unwrap().games.len());
+    }
+
+    #[actix_web::test]
+    async fn matchmaking_prefers_similar_ratings() {
+        let gm = GameManager::init();
+        let _novice = GameManager::matchmake(Arc::clone(&gm), Some(1200.0)).await;
+        let _master = GameManager::matchmake(Arc::clone(&gm), Some(1900.0)).await;
+        assert_eq!(2, gm.inner.read().unwrap().queue.len());
+        let _expert = GameManager::matchmake(Arc::clone(&gm), Some(1800.0)).await;
+        let queue = &gm.inner.read().unwrap().queue;
+        assert_eq!(1, queue.len());
+        assert_eq!(1200.0, queue[0].rating);
+    }
+
+    #[actix_web::test]
+    async fn rated_results_update_ratings() {
+        let accounts = Accounts::in_memory().unwrap();
+        let alice = accounts.register("alice", "correct horse").unwrap();
+        let bob = accounts.register("bob", "battery staple").unwrap();
+        let gm = GameManager::init_with_accounts(Some(accounts));
+        let _ = gm.newgame("rated".into(), GameSettings { rated: true, ..Default::default() });
+        let g = gm.getgame("rated".into()).unwrap();
+        let _s1 = g.join(Profile::default().with_user(alice.id, "alice"), None).await.unwrap();
+        let _s2 = g.join(Profile::default().with_user(bob.id, "bob"), None).await.unwrap();
+        let creds = [g.credentials_of(Player::X).unwrap(), g.credentials_of(Player::O).unwrap()];
+        for (i, field) in [0, 3, 1, 4, 2].into_iter().enumerate() {
+            assert!(g.addmove(field, creds[i % 2].clone()).await);
+        }
+        let accounts = gm.accounts().unwrap();
+        eventually(|| accounts.rating(bob.id).unwrap().losses == 1).await;
+        assert!(accounts.rating(alice.id).unwrap().rating > INITIAL_RATING);
+    }
+
+    #[actix_web::test]
+    async fn forfeits_count_for_the_ratings() {
+        let accounts = Accounts::in_memory().unwrap();
+        let alice = accounts.register("alice", "correct horse").unwrap();
+        let bob = accounts.register("bob", "battery staple").unwrap();
+        let gm = GameManager::init_with_accounts(Some(accounts));
+        let _ = gm.newgame("rated".into(), GameSettings { rated: true, ..Default::default() });
+        let g = gm.getgame("rated".into()).unwrap();
+        let _s1 = g.join(Profile::default().with_user(alice.id, "alice"), None).await.unwrap();
+        let _s2 = g.join(Profile::default().with_user(bob.id, "bob"), None).await.unwrap();
+        assert!(g.forfeit(&g.credentials_of(Player::X).unwrap()));
+        let accounts = gm.accounts().unwrap();
+        eventually(|| accounts.rating(alice.id).unwrap().losses == 1).await;
+        assert!(accounts.rating(bob.id).unwrap().rating > INITIAL_RATING);
+    }
+
+    #[actix_web::test]
+    async fn matchmaking_skips_players_who_left() {
+        let gm = GameManager::init();
+        let p1 = GameManager::matchmake(Arc::clone(&gm), None).await;
+        drop(p1);
+        let _p2 = GameManager::matchmake(Arc::clone(&gm), None).await;
+        assert_eq!(1, gm.inner.read().unwrap().queue.len());
+        assert!(gm.inner.read().unwrap().games.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn matchmaking_falls_back_to_bot() {
+        let gm = Arc::new_cyclic(|this| GameManager {
+            this: this.clone(),
+            inner: RwLock::new(GameManagerInner {
+                games: HashMap::new(),
+                queue: VecDeque::new(),
+                lobby_subscribers: Vec::new(),
+                tournaments: HashMap::new(),
+                tournament_games: HashMap::new(),
+                remote_bots: HashMap::new(),
+                engines: HashMap::new(),
+            }),
+            match_timeout: Duration::from_millis(10),
+            accounts: None,
+        });
+        let _p1 = GameManager::matchmake(Arc::clone(&gm), None).await;
+        actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        assert!(gm.inner.read().unwrap().queue.is_empty());
+        assert_eq!(1, gm.inner.read().unwrap().games.len());
+    }
+
+    #[actix_web::test]
+    async fn lobby_lists_public_games_only() {
+        let gm = GameManager::init();
+        let _ = gm.newgame("private".into(), GameSettings::default());
+        let _ = gm.newgame("public".into(), GameSettings { public: true, ..Default::default() });
+        let lobby = gm.lobby();
+        assert_eq!(1, lobby.len());
+        assert_eq!("public", lobby[0].gameid);
+    }
+
+    #[actix_web::test]
+    async fn lobby_subscribers_are_dropped_when_gone() {
+        let gm = GameManager::init();
+        let sub = gm.lobby_events().await;
+        let _ = gm.newgame("public".into(), GameSettings { public: true, ..Default::default() });
+        assert_eq!(1, gm.inner.read().unwrap().lobby_subscribers.len());
+        drop(sub);
+        gm.notify_lobby();
+        assert!(gm.inner.read().unwrap().lobby_subscribers.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn remote_bots_are_limited_per_account() {
+        let gm = GameManager::init();
+        for _ in 0..MAX_REMOTE_BOTS_PER_USER {
+            gm.register_remote_bot(1, "bot", "http://localhost:9000/move").unwrap();
+        }
+        assert_eq!(Err(RemoteError::Full), gm.register_remote_bot(1, "bot", "http://localhost:9000/move"));
+        assert!(gm.register_remote_bot(2, "bot", "http://localhost:9000/move").is_ok());
+        assert!(matches!(
+            gm.register_remote_bot(2, "bot", "http://localhost:22/"),
+            Err(RemoteError::PortNotAllowed(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn engines_are_limited_per_account() {
+        let gm = GameManager::init();
+        let script = "while read command rest; do case $command in tttp) echo tttpok;; quit) exit;; esac; done";
+        let start = || ExternalEngine::spawn("sh", &["-c".into(), script.into()], Duration::from_millis(10)).unwrap();
+        let engines: Vec<_> = (0..MAX_ENGINES_PER_USER).map(|_| gm.adopt_engine(1, start()).unwrap()).collect();
+        assert!(matches!(gm.adopt_engine(1, start()), Err(EngineError::Busy)));
+        assert!(gm.adopt_engine(2, start()).is_ok());
+        engines[0].stop();
+        assert!(gm.adopt_engine(1, start()).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn unused_remote_bots_are_forgotten() {
+        let gm = GameManager::init();
+        let idle = gm.register_remote_bot(1, "idle", "http://localhost:9000/move").unwrap();
+        let playing = gm.register_remote_bot(1, "playing", "http://localhost:9001/move").unwrap();
+        let bot = gm.remote_bot(&playing).unwrap();
+        let long_ago = Instant::now().checked_sub(REMOTE_BOT_TTL * 2).unwrap();
+        for entry in gm.inner.write().unwrap().remote_bots.values_mut() {
+            entry.last_used = long_ago;
+        }
+        gm.forget_unused_remote_bots();
+        assert!(gm.remote_bot(&idle).is_none());
+        assert!(gm.remote_bot(&playing).is_some());
+        drop(bot);
+    }
+
     #[actix_web::test]
     async fn empty_games_are_deleted() {
         let gm = GameManager::init();
-        gm.newgame("bar".into());
-        gm.remove_dead_games().await;
+        let _ = gm.newgame("bar".into(), GameSettings::default());
+        let _ = gm.remove_dead_games().await;
         assert!(gm.getgame("bar".into()).is_none());
     }
 
     #[actix_web::test]
     async fn non_empty_games_are_not_deleted() {
         let gm = GameManager::init();
-        gm.newgame("baz".into());
-        let p1 = gm.getgame("baz".into()).unwrap().join().await;
-        gm.remove_dead_games().await;
+        let _ = gm.newgame("baz".into(), GameSettings::default());
+        let p1 = gm.getgame("baz".into()).unwrap().join(Profile::default(), None).await.unwrap();
+        let _ = gm.remove_dead_games().await;
         assert!(gm.getgame("baz".into()).is_some());
         drop(p1);
-        gm.remove_dead_games().await;
+        let _ = gm.remove_dead_games().await;
         assert!(gm.getgame("baz".into()).is_none());
     }
+
+    #[actix_web::test]
+    async fn tournaments_move_on_without_no_shows() {
+        let gm = GameManager::init();
+        let t = gm.new_tournament("Final", Format::SingleElimination).unwrap();
+        let keys: Vec<String> = ["alice", "bob"]
+            .iter()
+            .map(|name| gm.enter_tournament(&t.id, name).unwrap())
+            .collect();
+        gm.start_tournament(&t.id, &t.organizer_key).unwrap();
+        let gameid = gm.standings(&t.id).unwrap().rounds[0][0].gameid.clone().unwrap();
+        let g = gm.getgame(gameid.clone()).unwrap();
+        let _s2 = g.join(Profile::default(), Some(&keys[1])).await.unwrap();
+        gm.forfeit_no_shows(TOURNAMENT_JOIN_TIMEOUT);
+        assert_eq!(Status::Running, gm.standings(&t.id).unwrap().status);
+        gm.forfeit_no_shows(Duration::ZERO);
+        let standings = gm.standings(&t.id).unwrap();
+        assert_eq!(Status::Finished, standings.status);
+        assert_eq!(Some("bob".to_string()), standings.winner);
+        assert!(gm.getgame(gameid).is_none());
+
+        gm.forget_idle_tournaments(TOURNAMENT_TTL);
+        assert!(gm.standings(&t.id).is_ok());
+        gm.forget_idle_tournaments(Duration::ZERO);
+        assert_eq!(Err(TournamentError::NotFound), gm.standings(&t.id).map(|_| ()));
+    }
+
+    #[actix_web::test]
+    async fn tournament_games_advance_the_bracket() {
+        let gm = GameManager::init();
+        let t = gm.new_tournament("Office cup", Format::SingleElimination).unwrap();
+        let keys: Vec<String> = ["alice", "bob", "carol"]
+            .iter()
+            .map(|name| gm.enter_tournament(&t.id, name).unwrap())
+            .collect();
+        let _sub = gm.tournament_events(&t.id).await.unwrap();
+        assert_eq!(Err(TournamentError::NotOrganizer), gm.start_tournament(&t.id, "guess"));
+        gm.start_tournament(&t.id, &t.organizer_key).unwrap();
+        assert_eq!(Err(TournamentError::AlreadyStarted), gm.enter_tournament(&t.id, "dave"));
+
+        let gameid = gm.standings(&t.id).unwrap().rounds[0][0].gameid.clone().unwrap();
+        let g = gm.getgame(gameid.clone()).unwrap();
+        // nobody joined yet, the game still stays around
+        let _ = gm.remove_dead_games().await;
+        assert!(gm.getgame(gameid.clone()).is_some());
+        let _s1 = g.join(Profile::default(), Some(&keys[0])).await.unwrap();
+        let _s2 = g.join(Profile::default(), Some(&keys[1])).await.unwrap();
+        let creds = [g.credentials_of(Player::X).unwrap(), g.credentials_of(Player::O).unwrap()];
+        for (i, field) in [0, 3, 1, 4, 2].into_iter().enumerate() {
+            assert!(g.addmove(field, creds[i % 2].clone()).await);
+        }
+        eventually(|| gm.standings(&t.id).unwrap().rounds.len() == 2).await;
+
+        let standings = gm.standings(&t.id).unwrap();
+        assert_eq!(2, standings.rounds.len());
+        assert_eq!("alice", standings.rounds[1][0].x);
+        assert_eq!(Some("carol".to_string()), standings.rounds[1][0].o);
+        assert!(standings.rounds[1][0].gameid.is_some());
+        assert_eq!(1, gm.inner.read().unwrap().tournaments[&t.id].subscribers.len());
+        assert_eq!(Err(TournamentError::NotFound), gm.standings("nope").map(|_| ()));
+    }
 }
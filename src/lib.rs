@@ -0,0 +1,13 @@
+/// Game logic, bots and persistence of the tic-tac-toe server, shared by its binaries
+pub mod accounts;
+pub mod bot;
+pub mod chat;
+pub mod game;
+pub mod gamemanager;
+pub mod profile;
+pub mod protocol;
+pub mod rating;
+pub mod reaction;
+pub mod replay;
+pub mod tictactoe;
+pub mod tournament;
@@ -2,43 +2,94 @@
 #![allow(unstable_features)]
 
 use actix_files::{self as fs, NamedFile};
+use multiplayer_tictactoe::{
+    accounts, bot, chat, game, gamemanager, profile, protocol, reaction, tictactoe, tournament,
+};
 use actix_session::{storage::CookieSessionStore, Session, SessionMiddleware};
 use actix_web::{
     cookie::Key,
+    dev::ServiceRequest,
     get,
     http::{header, StatusCode},
     middleware::Logger,
-    post, web, App, HttpResponse, HttpServer, Responder,
+    post, web, App, Either, HttpRequest, HttpResponse, HttpServer, Responder,
 };
+use accounts::{AccountError, Accounts, UserId};
+use bot::engine::{EngineError, ExternalEngine};
+use bot::remote::RemoteError;
+use bot::Bot;
+use chat::ChatError;
+use game::{GameSettings, HintError, JoinError, DEFAULT_HINTS};
 use gamemanager::GameManager;
 use nanoid::nanoid;
+use profile::Profile;
+use protocol::{Join, NewGame};
+use reaction::Reaction;
+use tictactoe::{analysis::Solver, tablebase::Tablebase, Board};
+use serde::Deserialize;
+use tournament::{Format, TournamentError};
 use std::{
     collections::HashMap,
     path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-pub mod game;
-pub mod gamemanager;
-pub mod tictactoe;
-
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
-    let gm = GameManager::init();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("error"));
+    log::info!("Solved {} positions", Tablebase::get().len());
+    let db_path = std::env::var("TICTACTOE_DB").unwrap_or_else(|_| "tictactoe.db".into());
+    let accounts = match Accounts::open(&db_path) {
+        Ok(a) => Some(a),
+        Err(e) => {
+            log::error!("Could not open account database {db_path}, running without accounts: {e}");
+            None
+        }
+    };
+    let gm = GameManager::init_with_accounts(accounts);
+    let session_key = session_key();
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::from(Arc::clone(&gm)))
             .service(index)
             .service(healthcheck)
+            .service(bots)
+            .service(register_remote_bot)
+            .service(analyse_position)
             .service(newgame)
+            .service(register)
+            .service(login)
+            .service(logout)
+            .service(me)
+            .service(leaderboard)
+            .service(matchmaking)
+            .service(lobby)
+            // must be registered before game_events, which would take "lobby" for a game id
+            .service(lobby_events)
+            // tournament routes must come before the game routes that would take "tournaments" for a game id
+            .service(new_tournament)
+            .service(enter_tournament)
+            .service(start_tournament)
+            .service(get_tournament)
+            .service(tournament_events)
             .service(game_events)
             .service(getgame)
+            .service(getrecord)
+            .service(game_analysis)
+            // must be registered before addmove, which would swallow the path
+            .service(postchat)
             .service(addmove)
             .service(rematch)
+            .service(hint)
+            .service(react)
             .service(fs::Files::new("/{gameid}", "client"))
-            .wrap(Logger::default())
+            .wrap(SessionMiddleware::new(
+                CookieSessionStore::default(),
+                session_key.clone(),
+            ))
+            .wrap(Logger::new(LOG_FORMAT).custom_request_replace("request", logged_request))
     })
     .bind(("0.0.0.0", 8080))?
     .workers(4)
@@ -46,9 +97,32 @@ async fn main() -> Result<(), std::io::Error> {
     .await
 }
 
+/// The format of `Logger::default`, with the request line of `logged_request`
+const LOG_FORMAT: &str = r#"%a "%{request}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
+/// The request line for the access log, passphrases travel in queries and stay out of it
+fn logged_request(req: &ServiceRequest) -> String {
+    let path = match req.query_string() {
+        "" => req.path().to_string(),
+        query => format!("{}?{}", req.path(), protocol::redact_query(query)),
+    };
+    format!("{} {} {:?}", req.method(), path, req.version())
+}
+
+/// Signs the session cookies, taken from SESSION_KEY (at least 64 bytes) so logins survive restarts
+fn session_key() -> Key {
+    match std::env::var("SESSION_KEY") {
+        Ok(key) if key.len() >= 64 => Key::from(key.as_bytes()),
+        _ => {
+            log::warn!("No usable SESSION_KEY set, logins will not survive a restart");
+            Key::generate()
+        }
+    }
+}
+
 #[get("/")]
 async fn index() -> impl Responder {
-    let file = format!("client/index.html");
+    let file = "client/index.html".to_string();
     let path: PathBuf = file.parse().unwrap();
     NamedFile::open(path).unwrap()
 }
@@ -58,11 +132,101 @@ async fn healthcheck() -> impl Responder {
     HttpResponse::Ok().finish()
 }
 
+/// The bots that can be picked for a new game, weakest first
+#[get("/bots")]
+async fn bots() -> impl Responder {
+    match web::block(bot::calibrated_personalities).await {
+        Ok(bots) => HttpResponse::Ok().json(bots),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+struct RemoteBotForm {
+    name: String,
+    url: String,
+}
+
+/// Register a bot behind an HTTP callback, new games play against it with `bot=remote:<id>`
+#[post("/bots/remote")]
+async fn register_remote_bot(
+    form: web::Json<RemoteBotForm>,
+    session: Session,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    let user = match logged_in(&session, &gm).await {
+        Some(user) => user,
+        None => return HttpResponse::Unauthorized().body("Log in to register a bot"),
+    };
+    match gm.register_remote_bot(user.id, &form.name, &form.url) {
+        Ok(id) => HttpResponse::Created().json(serde_json::json!({ "bot": format!("remote:{id}"), "id": id })),
+        Err(RemoteError::Full) => HttpResponse::ServiceUnavailable().finish(),
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
+    }
+}
+
+/// Time an external engine gets per move
+const ENGINE_MOVETIME: Duration = Duration::from_secs(1);
+
 #[get("/newgame")]
-async fn newgame(games: web::Data<GameManager>) -> impl Responder {
+async fn newgame(query: web::Query<NewGame>, session: Session, games: web::Data<GameManager>) -> impl Responder {
     let gameid = nanoid!(8);
     let gameurl = format!("{gameid}/game");
-    match games.newgame(gameid) {
+    let query = query.into_inner();
+    let position = match query.position.filter(|p| !p.is_empty()).map(|p| Board::from_notation(&p)) {
+        None => None,
+        Some(Ok(board)) if board.get_winner().is_none() => Some(board),
+        Some(Ok(_)) => return HttpResponse::BadRequest().body("This game is already over"),
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    let bot = match query.bot.filter(|b| !b.is_empty()) {
+        None => None,
+        Some(name) => match name.strip_prefix("engine:").map(String::from) {
+            Some(engine) => {
+                let user = match logged_in(&session, &games).await {
+                    Some(user) => user,
+                    None => return HttpResponse::Unauthorized().body("Log in to play against an engine"),
+                };
+                match web::block(move || ExternalEngine::configured(&engine, ENGINE_MOVETIME))
+                    .await
+                    .map(|started| started.and_then(|engine| games.adopt_engine(user.id, engine)))
+                {
+                    Ok(Ok(engine)) => Some(Bot::External(engine)),
+                    Ok(Err(e @ EngineError::NotConfigured(_))) => {
+                        return HttpResponse::BadRequest().body(e.to_string())
+                    }
+                    Ok(Err(e @ EngineError::Busy)) => {
+                        return HttpResponse::ServiceUnavailable().body(e.to_string())
+                    }
+                    Ok(Err(e)) => {
+                        log::error!("Could not start engine {name}: {e}");
+                        return HttpResponse::ServiceUnavailable().finish();
+                    }
+                    Err(_) => return HttpResponse::InternalServerError().finish(),
+                }
+            }
+            None => match name.strip_prefix("remote:").map(|id| games.remote_bot(id)) {
+                Some(Some(remote)) => Some(Bot::Remote(remote)),
+                Some(None) => return HttpResponse::BadRequest().body("Unknown bot"),
+                None => match name.parse::<Bot>() {
+                    Ok(bot) => Some(bot),
+                    Err(_) => return HttpResponse::BadRequest().body("Unknown bot"),
+                },
+            },
+        },
+    };
+    let settings = GameSettings {
+        public: query.public,
+        passphrase: query.passphrase.filter(|p| !p.is_empty()),
+        spectator_passphrase: query.spectator_passphrase.filter(|p| !p.is_empty()),
+        rated: query.rated,
+        position,
+        hints: query.hints.unwrap_or(DEFAULT_HINTS),
+        bot,
+        seed: query.seed,
+        ..Default::default()
+    };
+    match games.newgame(gameid, settings) {
         Ok(_) => HttpResponse::Found()
             .append_header((header::LOCATION, gameurl))
             .finish(),
@@ -70,6 +234,209 @@ async fn newgame(games: web::Data<GameManager>) -> impl Responder {
     }
 }
 
+#[derive(Deserialize)]
+struct LoginForm {
+    username: String,
+    password: String,
+}
+
+/// The user the session belongs to, if they are logged in and the server has accounts
+async fn logged_in(session: &Session, gm: &GameManager) -> Option<accounts::User> {
+    let id = session.get::<UserId>("user_id").ok().flatten()?;
+    let accounts = gm.accounts()?;
+    web::block(move || accounts.get(id)).await.ok()?.ok()?
+}
+
+fn account_error(e: AccountError) -> HttpResponse {
+    match e {
+        AccountError::InvalidUsername | AccountError::WeakPassword => {
+            HttpResponse::BadRequest().body(e.to_string())
+        }
+        AccountError::UsernameTaken => HttpResponse::Conflict().body(e.to_string()),
+        AccountError::WrongCredentials => HttpResponse::Unauthorized().body(e.to_string()),
+        AccountError::Hashing | AccountError::Database(_) => {
+            log::error!("Account error: {e}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/register")]
+async fn register(
+    form: web::Json<LoginForm>,
+    session: Session,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    let accounts = match gm.accounts() {
+        Some(a) => a,
+        None => return HttpResponse::ServiceUnavailable().finish(),
+    };
+    let form = form.into_inner();
+    match web::block(move || accounts.register(&form.username, &form.password)).await {
+        Ok(Ok(user)) => match session.insert("user_id", user.id) {
+            Ok(()) => HttpResponse::Ok().json(user),
+            Err(_) => HttpResponse::InternalServerError().finish(),
+        },
+        Ok(Err(e)) => account_error(e),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/login")]
+async fn login(
+    form: web::Json<LoginForm>,
+    session: Session,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    let accounts = match gm.accounts() {
+        Some(a) => a,
+        None => return HttpResponse::ServiceUnavailable().finish(),
+    };
+    let form = form.into_inner();
+    match web::block(move || accounts.login(&form.username, &form.password)).await {
+        Ok(Ok(user)) => {
+            session.renew();
+            match session.insert("user_id", user.id) {
+                Ok(()) => HttpResponse::Ok().json(user),
+                Err(_) => HttpResponse::InternalServerError().finish(),
+            }
+        }
+        Ok(Err(e)) => account_error(e),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/logout")]
+async fn logout(session: Session) -> impl Responder {
+    session.purge();
+    HttpResponse::Ok().finish()
+}
+
+#[get("/me")]
+async fn me(session: Session, gm: web::Data<GameManager>) -> impl Responder {
+    match logged_in(&session, &gm).await {
+        Some(user) => HttpResponse::Ok().json(user),
+        None => HttpResponse::Unauthorized().finish(),
+    }
+}
+
+/// How many players the leaderboard lists
+const LEADERBOARD_LEN: usize = 50;
+
+#[get("/leaderboard")]
+async fn leaderboard(gm: web::Data<GameManager>) -> impl Responder {
+    let accounts = match gm.accounts() {
+        Some(a) => a,
+        None => return HttpResponse::ServiceUnavailable().finish(),
+    };
+    match web::block(move || accounts.leaderboard(LEADERBOARD_LEN)).await {
+        Ok(Ok(standings)) => HttpResponse::Ok().json(standings),
+        Ok(Err(e)) => account_error(e),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[get("/matchmaking")]
+async fn matchmaking(session: Session, gm: web::Data<GameManager>) -> impl Responder {
+    let mut rating = None;
+    if let (Some(user), Some(accounts)) = (logged_in(&session, &gm).await, gm.accounts()) {
+        rating = web::block(move || accounts.rating(user.id))
+            .await
+            .ok()
+            .and_then(|r| r.ok())
+            .map(|r| r.rating);
+    }
+    GameManager::matchmake(gm.into_inner(), rating).await
+}
+
+#[get("/lobby")]
+async fn lobby(gm: web::Data<GameManager>) -> impl Responder {
+    web::Json(gm.lobby())
+}
+
+#[get("/lobby/events")]
+async fn lobby_events(gm: web::Data<GameManager>) -> impl Responder {
+    gm.lobby_events().await
+}
+
+#[derive(Deserialize)]
+struct NewTournamentForm {
+    name: String,
+    format: Format,
+}
+
+#[derive(Deserialize)]
+struct EntrantForm {
+    name: String,
+}
+
+fn tournament_error(e: TournamentError) -> HttpResponse {
+    match e {
+        TournamentError::InvalidName
+        | TournamentError::TooManyEntrants
+        | TournamentError::NotEnoughEntrants => HttpResponse::BadRequest().finish(),
+        TournamentError::AlreadyStarted => HttpResponse::Conflict().finish(),
+        TournamentError::NotOrganizer => HttpResponse::Forbidden().finish(),
+        TournamentError::NotFound => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[post("/tournaments")]
+async fn new_tournament(
+    form: web::Json<NewTournamentForm>,
+    session: Session,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    if logged_in(&session, &gm).await.is_none() {
+        return HttpResponse::Unauthorized().body("Log in to run a tournament");
+    }
+    match gm.new_tournament(&form.name, form.format) {
+        Ok(t) => HttpResponse::Created().json(t),
+        Err(e) => tournament_error(e),
+    }
+}
+
+/// Entrants get a key, it takes them to their seat when given as the passphrase of their games
+#[post("/tournaments/{id}/entrants")]
+async fn enter_tournament(
+    id: web::Path<String>,
+    form: web::Json<EntrantForm>,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    match gm.enter_tournament(&id, &form.name) {
+        Ok(key) => HttpResponse::Ok().json(serde_json::json!({ "key": key })),
+        Err(e) => tournament_error(e),
+    }
+}
+
+#[post("/tournaments/{id}/start/{organizer_key}")]
+async fn start_tournament(path: web::Path<(String, String)>, gm: web::Data<GameManager>) -> impl Responder {
+    let (id, organizer_key) = path.into_inner();
+    match gm.start_tournament(&id, &organizer_key) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => tournament_error(e),
+    }
+}
+
+#[get("/tournaments/{id}")]
+async fn get_tournament(id: web::Path<String>, gm: web::Data<GameManager>) -> impl Responder {
+    match gm.standings(&id) {
+        Ok(s) => HttpResponse::Ok().json(s),
+        Err(e) => tournament_error(e),
+    }
+}
+
+#[get("/tournaments/{id}/events")]
+async fn tournament_events(
+    id: web::Path<String>,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    match gm.tournament_events(&id).await {
+        Ok(stream) => Either::Left(stream),
+        Err(e) => Either::Right(tournament_error(e)),
+    }
+}
+
 #[get("/{game_id}/rematch/{credentials}")]
 async fn rematch(
     pathdata: web::Path<(String, String)>,
@@ -91,26 +458,129 @@ async fn rematch(
     }
 }
 
+/// A suggested move, for the eyes of the player asking only
+#[get("/{game_id}/hint/{credentials}")]
+async fn hint(path: web::Path<(String, String)>, gm: web::Data<GameManager>) -> impl Responder {
+    let (id, cred) = path.into_inner();
+    let g = match gm.getgame(id) {
+        Some(g) => g,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    match web::block(move || g.hint(&cred)).await {
+        Ok(Ok(hint)) => HttpResponse::Ok().json(hint),
+        Ok(Err(HintError::Disabled | HintError::UnknownPlayer)) => HttpResponse::Forbidden().finish(),
+        Ok(Err(HintError::BudgetSpent)) => HttpResponse::TooManyRequests().finish(),
+        Ok(Err(HintError::NotStarted | HintError::NotYourTurn | HintError::GameOver)) => {
+            HttpResponse::Conflict().finish()
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 #[get("/{game_id}/game")]
 async fn getgame(id: web::Path<String>) -> impl Responder {
     let id = id.into_inner();
-    let file = format!("client/client.html");
+    let file = "client/client.html".to_string();
     let path: PathBuf = file.parse().unwrap();
     NamedFile::open(path).unwrap()
 }
 
+/// The passphrase of a protected game, for requests besides joining it
+#[derive(Deserialize)]
+struct PassphraseQuery {
+    passphrase: Option<String>,
+}
+
+/// The game so far as a plain text record, see `tictactoe::record`
+#[get("/{game_id}/record")]
+async fn getrecord(
+    id: web::Path<String>,
+    query: web::Query<PassphraseQuery>,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    match gm.getgame(id.into_inner()) {
+        Some(g) if !g.may_watch(query.passphrase.as_deref()) => HttpResponse::Forbidden().finish(),
+        Some(g) => match g.record() {
+            Some(record) => HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .body(record.to_string()),
+            None => HttpResponse::NotFound().finish(),
+        },
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+struct AnalysisQuery {
+    position: String,
+}
+
+/// Every move of a position given in notation, with its value from a full search
+#[get("/analysis")]
+async fn analyse_position(query: web::Query<AnalysisQuery>) -> impl Responder {
+    match Board::from_notation(&query.position) {
+        Ok(board) => analysed(board).await,
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
+    }
+}
+
+/// The position of a finished game, along with a review of every move played
+#[get("/{game_id}/analysis")]
+async fn game_analysis(
+    id: web::Path<String>,
+    query: web::Query<PassphraseQuery>,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    match gm.getgame(id.into_inner()) {
+        Some(g) if !g.may_watch(query.passphrase.as_deref()) => HttpResponse::Forbidden().finish(),
+        Some(g) => match g.finished_board() {
+            Some(board) => analysed(board).await,
+            None => HttpResponse::Forbidden().body("The game is not over yet"),
+        },
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+async fn analysed(board: Board) -> HttpResponse {
+    match web::block(move || Solver::new().analyse(&board)).await {
+        Ok(analysis) => HttpResponse::Ok().json(analysis),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
 #[get("/{game_id}/events")]
-async fn game_events(id: web::Path<String>, gm: web::Data<GameManager>) -> impl Responder {
+async fn game_events(
+    req: HttpRequest,
+    id: web::Path<String>,
+    query: web::Query<Join>,
+    session: Session,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
     let id = id.into_inner();
+    // sent by browsers reconnecting on their own, they get the events they missed
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<u64>().ok());
+    let mut profile = Profile::new(query.name.as_deref(), query.avatar.as_deref());
+    if let Some(user) = logged_in(&session, &gm).await {
+        profile = profile.with_user(user.id, &user.username);
+    }
     match gm.getgame(id.clone()) {
-        Some(g) => {
-            let stream = g.join().await;
+        Some(g) => match g.rejoin(profile, query.passphrase.as_deref(), last_event_id).await {
+            Ok(stream) => {
+                if g.is_public() {
+                    gm.notify_lobby();
+                }
 
-            Some(stream)
-        }
+                Either::Left(stream)
+            }
+            Err(JoinError::WrongPassphrase) => Either::Right(HttpResponse::Forbidden().finish()),
+        },
         None => {
             log::error!("Could not find game!");
-            None
+            Either::Right(HttpResponse::NotFound().finish())
         }
     }
 }
@@ -122,10 +592,11 @@ async fn addmove(
 ) -> impl Responder {
     let (id, newmove, credentials) = path.into_inner();
 
-    match gm.getgame(id) {
+    match gm.getgame(id.clone()) {
         Some(g) => {
             if g.addmove(newmove, credentials).await {
                 g.show().await;
+                g.play_bots().await;
                 //g.notify_players().await;
                 HttpResponse::Ok().finish()
             } else {
@@ -135,3 +606,48 @@ async fn addmove(
         None => HttpResponse::NotFound().finish(),
     }
 }
+
+#[post("/{game_id}/chat/{credentials}")]
+async fn postchat(
+    path: web::Path<(String, String)>,
+    text: String,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    let (id, credentials) = path.into_inner();
+
+    match gm.getgame(id) {
+        Some(g) => match g.chat(credentials, text).await {
+            Ok(()) => HttpResponse::Ok().finish(),
+            Err(ChatError::EmptyMessage | ChatError::MessageTooLong) => {
+                HttpResponse::BadRequest().finish()
+            }
+            Err(ChatError::RateLimited) => HttpResponse::TooManyRequests().finish(),
+            Err(ChatError::UnknownSender) => HttpResponse::Forbidden().finish(),
+        },
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[post("/{game_id}/react/{reaction}/{credentials}")]
+async fn react(
+    path: web::Path<(String, String, String)>,
+    gm: web::Data<GameManager>,
+) -> impl Responder {
+    let (id, reaction, credentials) = path.into_inner();
+    let reaction: Reaction = match reaction.parse() {
+        Ok(r) => r,
+        Err(_) => {
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+
+    match gm.getgame(id) {
+        Some(g) => match g.react(credentials, reaction).await {
+            Ok(()) => HttpResponse::Ok().finish(),
+            Err(ChatError::RateLimited) => HttpResponse::TooManyRequests().finish(),
+            Err(_) => HttpResponse::Forbidden().finish(),
+        },
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
@@ -0,0 +1,131 @@
+/// Types and methods related to how players present themselves to others
+use crate::accounts::UserId;
+use crate::tictactoe::Player;
+use serde::Serialize;
+
+/// Longest nickname we keep, in characters
+pub const MAX_NAME_LEN: usize = 20;
+/// Longest avatar we keep, in characters (emoji sequences can span several)
+pub const MAX_AVATAR_LEN: usize = 8;
+
+/// Nickname and avatar a player picked when joining a game
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Profile {
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    /// The account the player is logged in with, never shown to others
+    #[serde(skip)]
+    pub user_id: Option<UserId>,
+}
+
+impl Profile {
+    /// Build a profile from raw user input, anything that does not survive sanitizing is dropped
+    pub fn new(name: Option<&str>, avatar: Option<&str>) -> Self {
+        Profile {
+            name: name.and_then(sanitize_name),
+            avatar: avatar.and_then(sanitize_avatar),
+            user_id: None,
+        }
+    }
+
+    /// Link the profile to an account, the username stands in for a missing nickname
+    pub fn with_user(mut self, id: UserId, username: &str) -> Self {
+        self.user_id = Some(id);
+        if self.name.is_none() {
+            self.name = Some(username.to_string());
+        }
+        self
+    }
+
+    /// The nickname, or "Player X" / "Player O" if the player did not pick one
+    pub fn display_name(&self, seat: Player) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => seat.to_string(),
+        }
+    }
+}
+
+/// Characters that can reorder or hide text when rendered
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}')
+}
+
+/// Removes control and markup characters, collapses whitespace and truncates to MAX_NAME_LEN
+/// Returns None if nothing is left
+pub fn sanitize_name(raw: &str) -> Option<String> {
+    let cleaned: String = raw
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control() && !is_invisible(*c))
+        .filter(|c| !matches!(c, '<' | '>' | '&' | '"' | '\'' | '`'))
+        .collect();
+    let collapsed = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name: String = collapsed.chars().take(MAX_NAME_LEN).collect();
+    let name = name.trim_end();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Accepts a short emoji (sequence), rejects anything with letters, digits, markup or whitespace
+pub fn sanitize_avatar(raw: &str) -> Option<String> {
+    let avatar = raw.trim();
+    if avatar.is_empty() || avatar.chars().count() > MAX_AVATAR_LEN {
+        return None;
+    }
+    let valid = avatar.chars().all(|c| {
+        !c.is_ascii()
+            && !c.is_control()
+            && !c.is_alphanumeric()
+            && !c.is_whitespace()
+            && (c == '\u{200D}' || !is_invisible(c))
+    });
+    if valid {
+        Some(avatar.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_sanitized() {
+        assert_eq!(Some("Alice".into()), sanitize_name("  Alice \n"));
+        assert_eq!(Some("Bob the Builder".into()), sanitize_name("Bob   the\tBuilder"));
+        assert_eq!(Some("bAlice/b".into()), sanitize_name("<b>Alice</b>"));
+        assert_eq!(None, sanitize_name(" \u{202E} "));
+        assert_eq!(MAX_NAME_LEN, sanitize_name(&"a".repeat(50)).unwrap().chars().count());
+    }
+
+    #[test]
+    fn only_emoji_are_valid_avatars() {
+        assert_eq!(Some("🦀".into()), sanitize_avatar("🦀"));
+        assert_eq!(Some("👩‍🚀".into()), sanitize_avatar(" 👩‍🚀 "));
+        assert_eq!(None, sanitize_avatar("abc"));
+        assert_eq!(None, sanitize_avatar("<b>"));
+        assert_eq!(None, sanitize_avatar("🦀🦀🦀🦀🦀🦀🦀🦀🦀"));
+    }
+
+    #[test]
+    fn display_name_falls_back_to_seat() {
+        let anonymous = Profile::new(Some("   "), None);
+        assert_eq!("Player O", anonymous.display_name(Player::O));
+        let named = Profile::new(Some("Carol"), Some("🐙"));
+        assert_eq!("Carol", named.display_name(Player::X));
+    }
+
+    #[test]
+    fn username_stands_in_for_missing_nickname() {
+        let linked = Profile::new(None, None).with_user(7, "carol");
+        assert_eq!("carol", linked.display_name(Player::X));
+        assert_eq!(Some(7), linked.user_id);
+        let nicknamed = Profile::new(Some("Cee"), None).with_user(7, "carol");
+        assert_eq!("Cee", nicknamed.display_name(Player::X));
+    }
+}
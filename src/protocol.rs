@@ -0,0 +1,415 @@
+/// The messages the server sends on its event streams and the requests clients send it
+///
+/// Every event is a `ServerMessage` in a `Versioned` envelope. The SSE event is named after the
+/// message and its data is the JSON of the envelope:
+///
+/// ```text
+/// event: notification
+/// data: {"version":1,"event":"notification","data":"Your turn, Ann!"}
+/// ```
+///
+/// Clients send `ClientMessage`s as the HTTP requests `ClientMessage::method` and `ClientMessage::path`
+/// name. `VERSION` goes up whenever a message changes in a way older clients cannot read.
+use crate::chat::ChatMessage;
+use crate::game::{GameInfo, GameSummary};
+use crate::gamemanager::Match;
+use crate::reaction::{Reaction, ReactionEvent};
+use crate::tournament::Standings;
+use actix_web_lab::sse;
+use serde::{Deserialize, Serialize};
+
+pub const VERSION: u32 = 1;
+
+/// A message with the version of the protocol it was written in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub message: T,
+}
+impl<T> Versioned<T> {
+    pub fn new(message: T) -> Self {
+        Versioned {
+            version: VERSION,
+            message,
+        }
+    }
+}
+
+/// What the server sends on the event streams of games, the lobby, matchmaking and tournaments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "lowercase")]
+pub enum ServerMessage {
+    /// The board and the players, after every change
+    State(GameInfo),
+    /// Needed to move, chat and ask for a rematch, sent once after joining
+    Credentials(String),
+    Notification(String),
+    /// Both seats are taken
+    StartGame,
+    Chat(ChatMessage),
+    Reaction(ReactionEvent),
+    /// A game was found for a player in the matchmaking queue
+    Matched(Match),
+    /// The public games, whenever they change
+    Lobby(Vec<GameSummary>),
+    Standings(Standings),
+}
+impl ServerMessage {
+    /// Name of the SSE event that carries the message
+    pub fn event(&self) -> &'static str {
+        match self {
+            Self::State(_) => "state",
+            Self::Credentials(_) => "credentials",
+            Self::Notification(_) => "notification",
+            Self::StartGame => "startgame",
+            Self::Chat(_) => "chat",
+            Self::Reaction(_) => "reaction",
+            Self::Matched(_) => "matched",
+            Self::Lobby(_) => "lobby",
+            Self::Standings(_) => "standings",
+        }
+    }
+
+    /// The message in the data of an event, messages of newer versions are refused
+    pub fn parse(data: &str) -> Result<Versioned<Self>, serde_json::Error> {
+        let message: Versioned<Self> = serde_json::from_str(data)?;
+        if message.version > VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "protocol version {} is not supported",
+                message.version
+            )));
+        }
+        Ok(message)
+    }
+}
+impl From<ServerMessage> for sse::Data {
+    fn from(message: ServerMessage) -> Self {
+        let event = message.event();
+        sse::Data::new(serde_json::to_string(&Versioned::new(message)).unwrap()).event(event)
+    }
+}
+impl From<ServerMessage> for sse::Event {
+    fn from(message: ServerMessage) -> Self {
+        sse::Data::from(message).into()
+    }
+}
+
+/// Options for a new game, all of them optional, sent as the query of `/newgame`
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NewGame {
+    #[serde(default)]
+    pub public: bool,
+    pub passphrase: Option<String>,
+    pub spectator_passphrase: Option<String>,
+    #[serde(default)]
+    pub rated: bool,
+    /// Start from this position instead of the empty board, see `Board::to_notation`
+    pub position: Option<String>,
+    /// Hints per player and game, 0 turns them off
+    pub hints: Option<usize>,
+    /// Play against this bot, see `Bot::from_str`, "engine:<name>" for an engine of `TICTACTOE_ENGINES`
+    /// or "remote:<id>" for a registered remote bot
+    pub bot: Option<String>,
+    /// Seed of the bot's random choices, to replay a game
+    pub seed: Option<u64>,
+}
+impl std::fmt::Debug for NewGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NewGame")
+            .field("public", &self.public)
+            .field(
+                "passphrase",
+                &self.passphrase.as_ref().map(|_| "<redacted>"),
+            )
+            .field(
+                "spectator_passphrase",
+                &self.spectator_passphrase.as_ref().map(|_| "<redacted>"),
+            )
+            .field("rated", &self.rated)
+            .field("position", &self.position)
+            .field("hints", &self.hints)
+            .field("bot", &self.bot)
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+/// Who joins a game, sent as the query of its event stream
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Join {
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    pub passphrase: Option<String>,
+}
+impl std::fmt::Debug for Join {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Join")
+            .field("name", &self.name)
+            .field("avatar", &self.avatar)
+            .field(
+                "passphrase",
+                &self.passphrase.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// The query of a request with the values of passphrases left out, for the logs
+pub fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if percent_decode(key).ends_with("passphrase") => {
+                format!("{key}=<redacted>")
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Undoes "%xx" escapes and "+" for spaces, as query strings are read
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let escaped = s
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (s.as_bytes()[i], escaped) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                bytes.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// What clients ask of the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", content = "data", rename_all = "lowercase")]
+pub enum ClientMessage {
+    NewGame(NewGame),
+    Join(Join),
+    /// Play the cell, counted from 0 in the top left corner
+    Move(usize),
+    Rematch,
+    Hint,
+    /// Posted as the body of the request
+    Chat(String),
+    React(Reaction),
+}
+impl ClientMessage {
+    pub fn method(&self) -> &'static str {
+        match self {
+            Self::NewGame(_) | Self::Join(_) | Self::Rematch | Self::Hint => "GET",
+            Self::Move(_) | Self::Chat(_) | Self::React(_) => "POST",
+        }
+    }
+
+    /// Path of the request in this game, the options of NewGame and Join go in its query
+    pub fn path(&self, game: &str, credentials: &str) -> String {
+        match self {
+            Self::NewGame(_) => "/newgame".into(),
+            Self::Join(_) => format!("/{game}/events"),
+            Self::Move(cell) => format!("/{game}/{cell}/{credentials}"),
+            Self::Rematch => format!("/{game}/rematch/{credentials}"),
+            Self::Hint => format!("/{game}/hint/{credentials}"),
+            Self::Chat(_) => format!("/{game}/chat/{credentials}"),
+            Self::React(reaction) => format!("/{game}/react/{}/{credentials}", reaction.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chat::Channel;
+    use crate::game::{GameStatus, PlayerInfo};
+    use crate::reaction::ReactionCounts;
+    use crate::tictactoe::Field;
+    use crate::tournament::{Format, PairingInfo, Record, Standing, Status};
+
+    fn ann() -> PlayerInfo {
+        PlayerInfo {
+            name: "Ann".into(),
+            avatar: Some("🦊".into()),
+            registered: true,
+        }
+    }
+
+    fn server_messages() -> Vec<ServerMessage> {
+        let mut reactions = ReactionCounts::default();
+        reactions.add(Reaction::GG);
+        let mut gamestate = [Field::Empty; 9];
+        gamestate[0] = Field::X;
+        gamestate[4] = Field::O;
+        vec![
+            ServerMessage::State(GameInfo {
+                gamestate,
+                outcome: None,
+                players: [Some(ann()), None],
+                reactions,
+            }),
+            ServerMessage::Credentials("V1StGXR8_Z5j".into()),
+            ServerMessage::Notification("Your turn, Ann!".into()),
+            ServerMessage::StartGame,
+            ServerMessage::Chat(ChatMessage {
+                channel: Channel::Players,
+                name: "Ann".into(),
+                avatar: None,
+                text: "gl hf".into(),
+            }),
+            ServerMessage::Reaction(ReactionEvent {
+                reaction: Reaction::Wow,
+                emoji: Reaction::Wow.emoji().into(),
+                name: "Ann".into(),
+            }),
+            ServerMessage::Matched(Match::new("abc123".into(), true)),
+            ServerMessage::Lobby(vec![GameSummary {
+                gameid: "abc123".into(),
+                url: "/abc123/game".into(),
+                status: GameStatus::Waiting,
+                seats_taken: 1,
+                seats_free: 1,
+                spectators: 0,
+                protected: false,
+                players: [Some(ann()), None],
+                age: 42,
+            }]),
+            ServerMessage::Standings(Standings {
+                name: "Friday cup".into(),
+                format: Format::RoundRobin,
+                status: Status::Running,
+                winner: None,
+                table: vec![Standing {
+                    name: "Ann".into(),
+                    points: 1.5,
+                    record: Record {
+                        wins: 1,
+                        draws: 1,
+                        losses: 0,
+                    },
+                }],
+                rounds: vec![vec![PairingInfo {
+                    x: "Ann".into(),
+                    o: Some("Bob".into()),
+                    gameid: Some("abc123".into()),
+                    url: Some("/abc123/game".into()),
+                    result: Some("draw".into()),
+                }]],
+            }),
+        ]
+    }
+
+    fn client_messages() -> Vec<ClientMessage> {
+        vec![
+            ClientMessage::NewGame(NewGame {
+                public: true,
+                bot: Some("perfect".into()),
+                seed: Some(7),
+                ..Default::default()
+            }),
+            ClientMessage::Join(Join {
+                name: Some("Ann".into()),
+                avatar: Some("🦊".into()),
+                passphrase: None,
+            }),
+            ClientMessage::Move(4),
+            ClientMessage::Rematch,
+            ClientMessage::Hint,
+            ClientMessage::Chat("gl hf".into()),
+            ClientMessage::React(Reaction::ThumbsUp),
+        ]
+    }
+
+    #[test]
+    fn server_messages_keep_their_shape() {
+        for message in server_messages() {
+            let json = serde_json::to_string_pretty(&Versioned::new(message.clone())).unwrap();
+            insta::assert_snapshot!(format!("server_{}", message.event()), json);
+            assert_eq!(
+                Versioned::new(message),
+                ServerMessage::parse(&json).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn client_messages_keep_their_shape() {
+        for message in client_messages() {
+            let json = serde_json::to_string_pretty(&Versioned::new(message.clone())).unwrap();
+            let name = serde_json::to_value(&message).unwrap()["request"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            insta::assert_snapshot!(format!("client_{name}"), json);
+            assert_eq!(
+                Versioned::new(message),
+                serde_json::from_str(&json).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn requests_go_where_the_server_routes_them() {
+        let paths: Vec<String> = client_messages()
+            .iter()
+            .map(|m| format!("{} {}", m.method(), m.path("abc123", "secret")))
+            .collect();
+        assert_eq!(
+            vec![
+                "GET /newgame",
+                "GET /abc123/events",
+                "POST /abc123/4/secret",
+                "GET /abc123/rematch/secret",
+                "GET /abc123/hint/secret",
+                "POST /abc123/chat/secret",
+                "POST /abc123/react/thumbsup/secret",
+            ],
+            paths
+        );
+    }
+
+    #[test]
+    fn passphrases_stay_out_of_the_logs() {
+        assert_eq!(
+            "name=Ann&passphrase=<redacted>",
+            redact_query("name=Ann&passphrase=open%20sesame")
+        );
+        assert_eq!(
+            "public=true&spectator_passphrase=<redacted>",
+            redact_query("public=true&spectator_passphrase=s3cret")
+        );
+        assert_eq!(
+            "pass%70hrase=<redacted>&spectator%5Fpassphrase=<redacted>",
+            redact_query("pass%70hrase=s3cret&spectator%5Fpassphrase=s3cret")
+        );
+        let join = Join {
+            passphrase: Some("open sesame".into()),
+            ..Default::default()
+        };
+        assert!(!format!("{join:?}").contains("sesame"));
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let data = r#"{"version":2,"event":"startgame"}"#;
+        assert!(ServerMessage::parse(data).is_err());
+        let data = r#"{"version":1,"event":"startgame"}"#;
+        assert_eq!(
+            ServerMessage::StartGame,
+            ServerMessage::parse(data).unwrap().message
+        );
+    }
+}
@@ -0,0 +1,192 @@
+/// Elo ratings for registered players and the leaderboard built from them
+use crate::accounts::{AccountError, Accounts, UserId};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+/// Where every player starts
+pub const INITIAL_RATING: f64 = 1500.0;
+/// How far a single game can move a rating
+pub const K_FACTOR: f64 = 32.0;
+
+/// The chance of scoring against `opponent`, a draw counting half
+pub fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+/// New ratings of both players after a game, `score` is 1 if the first player won,
+/// 0 if they lost and 0.5 for a draw
+pub fn updated_ratings(rating: f64, opponent: f64, score: f64) -> (f64, f64) {
+    let change = K_FACTOR * (score - expected_score(rating, opponent));
+    (rating + change, opponent - change)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rating {
+    pub rating: f64,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: INITIAL_RATING,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+}
+impl Rating {
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+    fn record(&mut self, rating: f64, score: f64) {
+        self.rating = rating;
+        if score > 0.5 {
+            self.wins += 1;
+        } else if score < 0.5 {
+            self.losses += 1;
+        } else {
+            self.draws += 1;
+        }
+    }
+}
+
+/// A line of the leaderboard
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Standing {
+    pub username: String,
+    #[serde(flatten)]
+    pub rating: Rating,
+}
+
+impl Accounts {
+    /// The rating of a user, players without rated games have the initial rating
+    pub fn rating(&self, id: UserId) -> Result<Rating, AccountError> {
+        stored_rating(&self.conn(), id)
+    }
+    /// Update both players' ratings after a rated game, `score` is from the first player's view
+    pub fn record_game(
+        &self,
+        player: UserId,
+        opponent: UserId,
+        score: f64,
+    ) -> Result<(Rating, Rating), AccountError> {
+        // read and write in one go, games of the same player may end at the same time
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let mut mine = stored_rating(&tx, player)?;
+        let mut theirs = stored_rating(&tx, opponent)?;
+        let (new_mine, new_theirs) = updated_ratings(mine.rating, theirs.rating, score);
+        mine.record(new_mine, score);
+        theirs.record(new_theirs, 1.0 - score);
+        for (id, rating) in [(player, &mine), (opponent, &theirs)] {
+            tx.execute(
+                "INSERT INTO ratings (user_id, rating, wins, losses, draws) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(user_id) DO UPDATE SET
+                    rating = excluded.rating, wins = excluded.wins,
+                    losses = excluded.losses, draws = excluded.draws",
+                params![id, rating.rating, rating.wins, rating.losses, rating.draws],
+            )?;
+        }
+        tx.commit()?;
+        Ok((mine, theirs))
+    }
+    /// The best rated players, highest first
+    pub fn leaderboard(&self, limit: usize) -> Result<Vec<Standing>, AccountError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT users.username, ratings.rating, ratings.wins, ratings.losses, ratings.draws
+             FROM ratings JOIN users ON users.id = ratings.user_id
+             ORDER BY ratings.rating DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok(Standing {
+                username: row.get(0)?,
+                rating: Rating {
+                    rating: row.get(1)?,
+                    wins: row.get(2)?,
+                    losses: row.get(3)?,
+                    draws: row.get(4)?,
+                },
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+/// The rating of a user as stored, or the initial one
+fn stored_rating(conn: &Connection, id: UserId) -> Result<Rating, AccountError> {
+    let rating = conn
+        .query_row(
+            "SELECT rating, wins, losses, draws FROM ratings WHERE user_id = ?1",
+            params![id],
+            |row| {
+                Ok(Rating {
+                    rating: row.get(0)?,
+                    wins: row.get(1)?,
+                    losses: row.get(2)?,
+                    draws: row.get(3)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(rating.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_players_trade_half_the_k_factor() {
+        assert_eq!(0.5, expected_score(1500.0, 1500.0));
+        let (winner, loser) = updated_ratings(1500.0, 1500.0, 1.0);
+        assert_eq!(INITIAL_RATING + K_FACTOR / 2.0, winner);
+        assert_eq!(INITIAL_RATING - K_FACTOR / 2.0, loser);
+        assert_eq!((1500.0, 1500.0), updated_ratings(1500.0, 1500.0, 0.5));
+    }
+
+    #[test]
+    fn upsets_move_ratings_more() {
+        let (underdog, _) = updated_ratings(1300.0, 1700.0, 1.0);
+        let (favourite, _) = updated_ratings(1700.0, 1300.0, 1.0);
+        assert!(underdog - 1300.0 > favourite - 1700.0);
+    }
+
+    #[test]
+    fn results_are_stored_and_ranked() {
+        let accounts = Accounts::in_memory().unwrap();
+        let alice = accounts.register("alice", "correct horse").unwrap();
+        let bob = accounts.register("bob", "battery staple").unwrap();
+        assert_eq!(Rating::default(), accounts.rating(alice.id).unwrap());
+        accounts.record_game(alice.id, bob.id, 1.0).unwrap();
+        accounts.record_game(bob.id, alice.id, 0.5).unwrap();
+        let alice_rating = accounts.rating(alice.id).unwrap();
+        assert_eq!((1, 0, 1), (alice_rating.wins, alice_rating.losses, alice_rating.draws));
+        assert!(alice_rating.rating > INITIAL_RATING);
+        let board = accounts.leaderboard(10).unwrap();
+        assert_eq!(vec!["alice", "bob"], board.iter().map(|s| s.username.as_str()).collect::<Vec<_>>());
+        assert_eq!(2, board[1].rating.games());
+    }
+
+    #[test]
+    fn games_ending_at_once_all_count() {
+        let accounts = Accounts::in_memory().unwrap();
+        let alice = accounts.register("alice", "correct horse").unwrap();
+        let bob = accounts.register("bob", "battery staple").unwrap();
+        let carol = accounts.register("carol", "hunter2 hunter2").unwrap();
+        std::thread::scope(|s| {
+            for opponent in [bob.id, carol.id] {
+                let accounts = &accounts;
+                s.spawn(move || {
+                    for _ in 0..20 {
+                        accounts.record_game(alice.id, opponent, 1.0).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(40, accounts.rating(alice.id).unwrap().wins);
+    }
+}
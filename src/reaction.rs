@@ -0,0 +1,114 @@
+/// Types related to quick reactions players and spectators can fire during a game
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How many reactions a single sender may fire within REACTION_WINDOW
+pub const REACTION_LIMIT: usize = 3;
+pub const REACTION_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reaction {
+    ThumbsUp,
+    Wow,
+    Laugh,
+    GG,
+}
+impl Reaction {
+    pub const ALL: [Reaction; 4] = [Reaction::ThumbsUp, Reaction::Wow, Reaction::Laugh, Reaction::GG];
+
+    /// How the reaction is named in URLs and messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reaction::ThumbsUp => "thumbsup",
+            Reaction::Wow => "wow",
+            Reaction::Laugh => "laugh",
+            Reaction::GG => "gg",
+        }
+    }
+
+    pub fn emoji(&self) -> &'static str {
+        match self {
+            Reaction::ThumbsUp => "👍",
+            Reaction::Wow => "😮",
+            Reaction::Laugh => "😂",
+            Reaction::GG => "GG",
+        }
+    }
+}
+impl FromStr for Reaction {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_lowercase().as_str() {
+            "thumbsup" => Ok(Reaction::ThumbsUp),
+            "wow" => Ok(Reaction::Wow),
+            "laugh" => Ok(Reaction::Laugh),
+            "gg" => Ok(Reaction::GG),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What gets sent to everyone in the game when someone reacts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReactionEvent {
+    pub reaction: Reaction,
+    pub emoji: String,
+    pub name: String,
+}
+
+/// How often each reaction was fired in a game
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReactionCounts(BTreeMap<Reaction, usize>);
+impl ReactionCounts {
+    pub fn add(&mut self, reaction: Reaction) {
+        *self.0.entry(reaction).or_insert(0) += 1;
+    }
+    pub fn get(&self, reaction: Reaction) -> usize {
+        self.0.get(&reaction).copied().unwrap_or(0)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+/// Lists the counts like "wow 1, gg 2", as in the `Reactions` header of records
+impl Display for ReactionCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (reaction, count)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {count}", reaction.name())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reactions_parse_from_their_serialized_name() {
+        for reaction in Reaction::ALL {
+            let name = serde_json::to_string(&reaction).unwrap();
+            assert_eq!(Ok(reaction), name.trim_matches('"').parse());
+        }
+        assert!("clap".parse::<Reaction>().is_err());
+    }
+
+    #[test]
+    fn counts_serialize_by_name() {
+        let mut counts = ReactionCounts::default();
+        counts.add(Reaction::GG);
+        counts.add(Reaction::GG);
+        counts.add(Reaction::Wow);
+        assert_eq!(2, counts.get(Reaction::GG));
+        assert_eq!(0, counts.get(Reaction::Laugh));
+        assert_eq!(r#"{"wow":1,"gg":2}"#, serde_json::to_string(&counts).unwrap());
+        assert_eq!("wow 1, gg 2", counts.to_string());
+    }
+}
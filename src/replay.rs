@@ -0,0 +1,151 @@
+/// Numbering the events of a game and keeping the recent ones for clients that reconnect
+///
+/// Every event a game sends carries the next id of its `Replay`. Browsers send the last id they
+/// saw as `Last-Event-ID` when they reconnect, and get the kept events after it sent again.
+use crate::chat::Channel;
+use crate::protocol::ServerMessage;
+use crate::tictactoe::Player;
+use actix_web_lab::sse;
+use std::collections::VecDeque;
+
+/// How many events are kept for reconnecting clients
+pub const REPLAY_LEN: usize = 64;
+
+/// Who gets to see an event
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Audience {
+    Everyone,
+    Players,
+    Spectators,
+    /// The player in this seat
+    Seat(Player),
+}
+impl Audience {
+    /// Whether the player in `seat` (or a spectator, if None) sees events for this audience
+    pub fn includes(&self, seat: Option<Player>) -> bool {
+        match self {
+            Audience::Everyone => true,
+            Audience::Players => seat.is_some(),
+            Audience::Spectators => seat.is_none(),
+            Audience::Seat(s) => seat == Some(*s),
+        }
+    }
+}
+impl From<Channel> for Audience {
+    fn from(channel: Channel) -> Self {
+        match channel {
+            Channel::Players => Audience::Everyone,
+            Channel::Spectators => Audience::Spectators,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    /// The id of the last event sent, ids start at 1
+    last_id: u64,
+    kept: VecDeque<(u64, Audience, ServerMessage)>,
+    /// The id of the newest event that no longer fits in `kept`
+    forgotten: u64,
+}
+impl Replay {
+    pub fn new() -> Self {
+        Replay::default()
+    }
+    /// Numbers an event meant for one stream only, like credentials, it is not replayed
+    pub fn number(&mut self, message: ServerMessage) -> sse::Data {
+        self.last_id += 1;
+        sse::Data::from(message).id(self.last_id.to_string())
+    }
+    /// Numbers an event and keeps it for those in `audience` who reconnect
+    pub fn record(&mut self, audience: Audience, message: ServerMessage) -> sse::Data {
+        self.last_id += 1;
+        if self.kept.len() == REPLAY_LEN {
+            if let Some((id, _, _)) = self.kept.pop_front() {
+                self.forgotten = id;
+            }
+        }
+        self.kept
+            .push_back((self.last_id, audience, message.clone()));
+        sse::Data::from(message).id(self.last_id.to_string())
+    }
+    /// The kept events after `last_id` that the player in `seat` (or a spectator) sees,
+    /// None if some of them are no longer kept
+    pub fn since(&self, last_id: u64, seat: Option<Player>) -> Option<Vec<sse::Data>> {
+        if last_id < self.forgotten {
+            return None;
+        }
+        Some(
+            self.kept
+                .iter()
+                .filter(|(id, audience, _)| *id > last_id && audience.includes(seat))
+                .map(|(id, _, message)| sse::Data::from(message.clone()).id(id.to_string()))
+                .collect(),
+        )
+    }
+    /// Drops the kept events for whoever sat in `seat`, so the next one to sit there does not get them
+    pub fn forget_seat(&mut self, seat: Player) {
+        self.kept.retain(|(_, audience, _)| *audience != Audience::Seat(seat));
+    }
+    /// The kept messages, oldest first
+    pub fn messages(&self) -> impl Iterator<Item = &ServerMessage> {
+        self.kept.iter().map(|(_, _, message)| message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(text: &str) -> ServerMessage {
+        ServerMessage::Notification(text.into())
+    }
+
+    #[test]
+    fn ids_go_up_across_kept_and_private_events() {
+        let mut replay = Replay::new();
+        let _ = replay.record(Audience::Everyone, note("first"));
+        let _ = replay.number(ServerMessage::Credentials("secret".into()));
+        let _ = replay.record(Audience::Everyone, note("third"));
+        assert_eq!(3, replay.last_id);
+        assert_eq!(1, replay.since(1, None).unwrap().len());
+        assert_eq!(2, replay.since(0, None).unwrap().len());
+        assert!(replay.since(3, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn events_are_replayed_to_their_audience_only() {
+        let mut replay = Replay::new();
+        let _ = replay.record(Audience::Everyone, note("everyone"));
+        let _ = replay.record(Audience::Players, note("players"));
+        let _ = replay.record(Audience::Spectators, note("spectators"));
+        let _ = replay.record(Audience::Seat(Player::X), note("x"));
+        let _ = replay.record(Channel::Spectators.into(), note("spectator chat"));
+        assert_eq!(3, replay.since(0, Some(Player::X)).unwrap().len());
+        assert_eq!(2, replay.since(0, Some(Player::O)).unwrap().len());
+        assert_eq!(3, replay.since(0, None).unwrap().len());
+    }
+
+    #[test]
+    fn seats_forget_their_events() {
+        let mut replay = Replay::new();
+        let _ = replay.record(Audience::Seat(Player::X), note("x"));
+        let _ = replay.record(Audience::Seat(Player::O), note("o"));
+        let _ = replay.record(Audience::Players, note("players"));
+        replay.forget_seat(Player::X);
+        assert_eq!(1, replay.since(0, Some(Player::X)).unwrap().len());
+        assert_eq!(2, replay.since(0, Some(Player::O)).unwrap().len());
+    }
+
+    #[test]
+    fn old_events_are_forgotten() {
+        let mut replay = Replay::new();
+        for i in 0..REPLAY_LEN + 2 {
+            let _ = replay.record(Audience::Everyone, note(&i.to_string()));
+        }
+        assert_eq!(REPLAY_LEN, replay.messages().count());
+        assert_eq!(Some(&note("2")), replay.messages().next());
+        assert!(replay.since(1, None).is_none());
+        assert_eq!(REPLAY_LEN, replay.since(2, None).unwrap().len());
+    }
+}
@@ -1,36 +1,50 @@
 /// Module to store Types and logic related to the Game
 use rand::seq::SliceRandom;
-use serde::Serialize;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use std::str::FromStr;
 
-pub fn best_next_move(b: &Board, lvl: &Difficulty) -> usize {
-    let winconditions = [
-        [0, 1, 2],
-        [3, 4, 5],
-        [6, 7, 8], // horizontal
-        [0, 3, 6],
-        [1, 4, 7],
-        [2, 5, 8], // vertical
-        [0, 4, 8],
-        [2, 4, 6],
-    ]; // diagonal
-
-    let me: Field;
-    let other: Field;
+pub mod analysis;
+pub mod grid;
+pub mod mcts;
+pub mod record;
+pub mod tablebase;
+
+/// The lines that win the game
+const WINCONDITIONS: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8], // horizontal
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8], // vertical
+    [0, 4, 8],
+    [2, 4, 6], // diagonal
+];
+
+/// The eight rotations and reflections of the board, as the cell each cell is taken from
+pub const SYMMETRIES: [[usize; 9]; 8] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8], // identity
+    [6, 3, 0, 7, 4, 1, 8, 5, 2], // rotated by 90 degrees
+    [8, 7, 6, 5, 4, 3, 2, 1, 0], // rotated by 180 degrees
+    [2, 5, 8, 1, 4, 7, 0, 3, 6], // rotated by 270 degrees
+    [2, 1, 0, 5, 4, 3, 8, 7, 6], // mirrored left to right
+    [6, 7, 8, 3, 4, 5, 0, 1, 2], // mirrored top to bottom
+    [0, 3, 6, 1, 4, 7, 2, 5, 8], // mirrored along the main diagonal
+    [8, 5, 2, 7, 4, 1, 6, 3, 0], // mirrored along the other diagonal
+];
+
+/// The move the builtin bot plays at a level, its random choices are drawn from `rng`
+pub fn best_next_move(b: &Board, lvl: &Difficulty, rng: &mut impl Rng) -> usize {
     let mut block: usize = 10;
-    match b.next_turn {
-        Player::X => {
-            me = Field::X;
-            other = Field::O
-        }
-        Player::O => {
-            me = Field::O;
-            other = Field::X
-        }
-    }
+    let (me, other) = match b.next_turn {
+        Player::X => (Field::X, Field::O),
+        Player::O => (Field::O, Field::X),
+    };
 
     // first check whether we can win this turn
-    for condition in winconditions {
+    for condition in WINCONDITIONS {
         let mut curr: Vec<Field> = Vec::new();
         for index in condition {
             curr.push(*b.fields.get(index).unwrap());
@@ -68,10 +82,9 @@ pub fn best_next_move(b: &Board, lvl: &Difficulty) -> usize {
         return 4;
     }
     // if we can't have the center, we prefer a corner if we can have it
-    let mut rng = rand::thread_rng();
     if lvl.prefer_corners {
         let mut pref = [0, 2, 6, 8];
-        pref.shuffle(&mut rng);
+        pref.shuffle(rng);
         for index in pref {
             if b.fields[index] == Field::Empty {
                 return index;
@@ -79,8 +92,8 @@ pub fn best_next_move(b: &Board, lvl: &Difficulty) -> usize {
         }
     }
     // finally, we pick at random
-    let mut pref = [1, 2, 3, 4, 5, 6, 7, 8];
-    pref.shuffle(&mut rng);
+    let mut pref = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+    pref.shuffle(rng);
     for index in pref {
         if b.fields[index] == Field::Empty {
             return index;
@@ -89,6 +102,8 @@ pub fn best_next_move(b: &Board, lvl: &Difficulty) -> usize {
     11 // if we return >8  something is wrong, we should have exhausted all possibilities by now
 }
 
+/// What the builtin bot looks for, each level knows everything the levels below it know
+#[derive(Debug, Clone, PartialEq)]
 pub struct Difficulty {
     block: bool,
     prefer_center: bool,
@@ -97,7 +112,8 @@ pub struct Difficulty {
 }
 
 impl Difficulty {
-    fn hardest() -> Self {
+    /// Wins when it can, blocks, and takes the center and then the corners
+    pub fn hardest() -> Self {
         Difficulty {
             block: true,
             prefer_center: true,
@@ -106,25 +122,28 @@ impl Difficulty {
         }
     }
 
-    fn hard() -> Self {
+    /// Wins when it can, blocks, and takes the center
+    pub fn hard() -> Self {
         Difficulty {
             block: true,
-            prefer_center: false,
+            prefer_center: true,
             prefer_corners: false,
             take_win: true,
         }
     }
 
-    fn medium() -> Self {
+    /// Wins when it can and blocks
+    pub fn medium() -> Self {
         Difficulty {
-            block: false,
-            prefer_center: true,
-            prefer_corners: true,
+            block: true,
+            prefer_center: false,
+            prefer_corners: false,
             take_win: true,
         }
     }
 
-    fn beatable() -> Self {
+    /// Wins when it can and likes corners, but never blocks
+    pub fn beatable() -> Self {
         Difficulty {
             block: false,
             prefer_center: false,
@@ -133,7 +152,8 @@ impl Difficulty {
         }
     }
 
-    fn easy() -> Self {
+    /// Wins when it can, otherwise plays at random
+    pub fn easy() -> Self {
         Difficulty {
             block: false,
             prefer_center: false,
@@ -142,7 +162,8 @@ impl Difficulty {
         }
     }
 
-    fn easiest() -> Self {
+    /// Plays at random
+    pub fn easiest() -> Self {
         Difficulty {
             block: false,
             prefer_center: false,
@@ -152,7 +173,23 @@ impl Difficulty {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
+/// Levels by name, from "easiest" to "hardest"
+impl FromStr for Difficulty {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "easiest" => Ok(Difficulty::easiest()),
+            "easy" => Ok(Difficulty::easy()),
+            "beatable" => Ok(Difficulty::beatable()),
+            "medium" => Ok(Difficulty::medium()),
+            "hard" => Ok(Difficulty::hard()),
+            "hardest" => Ok(Difficulty::hardest()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum Field {
     X,
     O,
@@ -168,7 +205,7 @@ impl Display for Field {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize)]
 pub enum Player {
     X,
     O,
@@ -201,10 +238,44 @@ impl std::convert::TryFrom<Field> for Player {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotationError {
+    /// Not three rows of three cells, or no side to move
+    Malformed,
+    /// X moves first, so X has as many pieces as O or one more
+    PieceCount,
+    /// The side to move does not follow from the pieces on the board
+    SideToMove,
+    /// Both players have three in a row
+    DoubleWinner,
+    /// The winner is not the player who moved last
+    PlayedOnAfterWin,
+}
+impl Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "Positions look like X.O/.X./O.. o"),
+            Self::PieceCount => write!(f, "X needs as many pieces as O or one more"),
+            Self::SideToMove => write!(f, "The side to move does not match the pieces"),
+            Self::DoubleWinner => write!(f, "Both players cannot have three in a row"),
+            Self::PlayedOnAfterWin => write!(f, "The game went on after it was won"),
+        }
+    }
+}
+impl std::error::Error for NotationError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Board {
     fields: [Field; 9],
     pub next_turn: Player,
+    /// Cells played so far, in order
+    history: Vec<usize>,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Board {
@@ -212,11 +283,12 @@ impl Board {
         Board {
             fields: [Field::Empty; 9],
             next_turn: Player::X,
+            history: Vec::new(),
         }
     }
 
     pub fn add_turn(&mut self, position: usize) -> bool {
-        if position > 8 || self.fields[position] != Field::Empty || self.get_winner() != None {
+        if position > 8 || self.fields[position] != Field::Empty || self.get_winner().is_some() {
             false
         } else {
             match self.next_turn {
@@ -224,10 +296,150 @@ impl Board {
                 Player::O => self.fields[position] = Field::O,
             }
             self.next_turn = !self.next_turn;
+            self.history.push(position);
             true
         }
     }
 
+    /// The cells played on this board, in order
+    pub fn history(&self) -> &[usize] {
+        &self.history
+    }
+
+    /// The position the history starts from, the empty board unless the game was set up
+    pub fn start(&self) -> Board {
+        let mut fields = self.fields;
+        for cell in &self.history {
+            fields[*cell] = Field::Empty;
+        }
+        let next_turn = if self.history.len().is_multiple_of(2) { self.next_turn } else { !self.next_turn };
+        Board {
+            fields,
+            next_turn,
+            history: Vec::new(),
+        }
+    }
+
+    /// The fields seen through a symmetry
+    fn transformed(&self, symmetry: &[usize; 9]) -> [Field; 9] {
+        symmetry.map(|from| self.fields[from])
+    }
+
+    /// The fields in base 3, empty cells are 0, X is 1 and O is 2
+    fn encode(fields: &[Field; 9]) -> u32 {
+        fields.iter().fold(0, |acc, f| {
+            acc * 3
+                + match f {
+                    Field::Empty => 0,
+                    Field::X => 1,
+                    Field::O => 2,
+                }
+        })
+    }
+
+    /// Same for every rotation and reflection of a position and different for any other position,
+    /// the side to move follows from the pieces
+    pub fn canonical_hash(&self) -> u32 {
+        SYMMETRIES
+            .iter()
+            .map(|s| Board::encode(&self.transformed(s)))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// The rotation or reflection of this position with the lowest hash, without history
+    pub fn canonical(&self) -> Board {
+        let fields = SYMMETRIES
+            .iter()
+            .map(|s| self.transformed(s))
+            .min_by_key(Board::encode)
+            .unwrap_or(self.fields);
+        Board {
+            fields,
+            next_turn: self.next_turn,
+            history: Vec::new(),
+        }
+    }
+
+    /// A position in notation: rows from top to bottom separated by '/', '.' for an empty cell,
+    /// then the side to move, e.g. "X.O/.X./O.. o"
+    pub fn to_notation(&self) -> String {
+        let rows: Vec<String> = self
+            .fields
+            .chunks(3)
+            .map(|row| {
+                row.iter()
+                    .map(|f| match f {
+                        Field::X => 'X',
+                        Field::O => 'O',
+                        Field::Empty => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+        let side = match self.next_turn {
+            Player::X => 'x',
+            Player::O => 'o',
+        };
+        format!("{} {side}", rows.join("/"))
+    }
+
+    /// Parses and validates a position written by `to_notation`, the board has no history
+    pub fn from_notation(notation: &str) -> Result<Board, NotationError> {
+        let (rows, side) = notation.trim().split_once(' ').ok_or(NotationError::Malformed)?;
+        let next_turn = match side.trim() {
+            "x" | "X" => Player::X,
+            "o" | "O" => Player::O,
+            _ => return Err(NotationError::Malformed),
+        };
+        let rows: Vec<&str> = rows.split('/').collect();
+        if rows.len() != 3 || rows.iter().any(|r| r.chars().count() != 3) {
+            return Err(NotationError::Malformed);
+        }
+        let mut fields = [Field::Empty; 9];
+        for (i, c) in rows.concat().chars().enumerate() {
+            fields[i] = match c {
+                'X' | 'x' => Field::X,
+                'O' | 'o' => Field::O,
+                '.' => Field::Empty,
+                _ => return Err(NotationError::Malformed),
+            };
+        }
+        Board::from_fields(fields, Some(next_turn))
+    }
+
+    /// A position without history, checked to be reachable in a game
+    /// The side to move follows from the pieces, if it is given it needs to match them
+    fn from_fields(fields: [Field; 9], next_turn: Option<Player>) -> Result<Board, NotationError> {
+        let xs = fields.iter().filter(|f| **f == Field::X).count();
+        let os = fields.iter().filter(|f| **f == Field::O).count();
+        let expected = match xs.checked_sub(os) {
+            Some(0) => Player::X,
+            Some(1) => Player::O,
+            _ => return Err(NotationError::PieceCount),
+        };
+        if next_turn.is_some_and(|p| p != expected) {
+            return Err(NotationError::SideToMove);
+        }
+        let board = Board {
+            fields,
+            next_turn: expected,
+            history: Vec::new(),
+        };
+        let line = |field: Field| {
+            WINCONDITIONS
+                .iter()
+                .any(|c| c.iter().all(|i| board.fields[*i] == field))
+        };
+        match (line(Field::X), line(Field::O)) {
+            (true, true) => Err(NotationError::DoubleWinner),
+            // whoever won made the last move
+            (true, false) if expected != Player::O => Err(NotationError::PlayedOnAfterWin),
+            (false, true) if expected != Player::X => Err(NotationError::PlayedOnAfterWin),
+            _ => Ok(board),
+        }
+    }
+
     pub fn show(&self) -> [Field; 9] {
         self.fields
         /*  format!(
@@ -246,18 +458,8 @@ impl Board {
     /// If theres a winner, returns Some(Winnerfield, wincondition), Some(Field::Empty, 10) for draw
     /// None if the game is undecided
     pub fn get_winner(&self) -> Option<(Field, usize)> {
-        let winconditions = [
-            [0, 1, 2],
-            [3, 4, 5],
-            [6, 7, 8], // horizontal
-            [0, 3, 6],
-            [1, 4, 7],
-            [2, 5, 8], // vertical
-            [0, 4, 8],
-            [2, 4, 6],
-        ]; // diagonal
         let mut blocked: usize = 0;
-        for (i, condition) in winconditions.iter().enumerate() {
+        for (i, condition) in WINCONDITIONS.iter().enumerate() {
             let mut curr: Vec<Field> = Vec::new();
             for index in *condition {
                 curr.push(*self.fields.get(index).unwrap());
@@ -280,7 +482,7 @@ impl Board {
                 }
             }
         }
-        if blocked == winconditions.len() {
+        if blocked == WINCONDITIONS.len() {
             // it's a draw
             Some((Field::Empty, 10))
         } else {
@@ -293,6 +495,25 @@ impl Board {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn seeded_moves_repeat() {
+        let board = Board::new();
+        let moves = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let lvl = Difficulty::easiest();
+            (0..20).map(|_| best_next_move(&board, &lvl, &mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(moves(3), moves(3));
+        assert_ne!(moves(3), moves(4));
+        // the center comes first, corners next
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(4, best_next_move(&board, &Difficulty::hardest(), &mut rng));
+        let board = Board::from_notation(".../.X./... o").unwrap();
+        assert!([0, 2, 6, 8].contains(&best_next_move(&board, &Difficulty::hardest(), &mut rng)));
+    }
 
     #[test]
     fn x_is_first() {
@@ -314,4 +535,82 @@ mod tests {
         assert!(!game.add_turn(0));
         assert_eq!(game.fields[0], Field::X);
     }
+
+    #[test]
+    fn notation_round_trips() {
+        let mut game = Board::new();
+        assert_eq!(".../.../... x", game.to_notation());
+        for cell in [4, 0, 2, 6] {
+            game.add_turn(cell);
+            let parsed = Board::from_notation(&game.to_notation()).unwrap();
+            assert_eq!(game.show(), parsed.show());
+            assert_eq!(game.next_turn, parsed.next_turn);
+        }
+        assert_eq!("O.X/.X./O.. x", game.to_notation());
+    }
+
+    #[test]
+    fn notation_is_validated() {
+        assert_eq!(Err(NotationError::Malformed), Board::from_notation("X.O/.X./O.."));
+        assert_eq!(Err(NotationError::Malformed), Board::from_notation("X.O/.X/O.. o"));
+        assert_eq!(Err(NotationError::Malformed), Board::from_notation("X.O/.Y./O.. o"));
+        assert_eq!(Err(NotationError::PieceCount), Board::from_notation("XXO/.X./... o"));
+        assert_eq!(Err(NotationError::PieceCount), Board::from_notation("OO./.../... x"));
+        assert_eq!(Err(NotationError::SideToMove), Board::from_notation("X../.../... x"));
+        assert_eq!(Err(NotationError::DoubleWinner), Board::from_notation("XXX/OOO/X.. o"));
+        assert_eq!(Err(NotationError::PlayedOnAfterWin), Board::from_notation("XXX/OO./O.. x"));
+        assert!(Board::from_notation("XXX/OO./... o").unwrap().get_winner().is_some());
+    }
+
+    #[test]
+    fn symmetric_positions_share_a_canonical_form() {
+        let board = Board::from_notation("XO./.X./... o").unwrap();
+        let canonical = board.canonical();
+        for symmetry in SYMMETRIES {
+            let turned = Board {
+                fields: board.transformed(&symmetry),
+                next_turn: board.next_turn,
+                history: Vec::new(),
+            };
+            assert_eq!(board.canonical_hash(), turned.canonical_hash());
+            assert_eq!(canonical, turned.canonical());
+        }
+        assert_eq!(canonical, canonical.canonical());
+        assert_eq!(board.canonical_hash(), Board::encode(&canonical.fields));
+        // a corner and an edge opening are different positions
+        let corner = Board::from_notation("X../.../... o").unwrap();
+        let edge = Board::from_notation(".X./.../... o").unwrap();
+        assert_ne!(corner.canonical_hash(), edge.canonical_hash());
+    }
+
+    #[test]
+    fn symmetries_are_distinct_permutations() {
+        for (i, a) in SYMMETRIES.iter().enumerate() {
+            let mut cells = *a;
+            cells.sort();
+            assert_eq!([0, 1, 2, 3, 4, 5, 6, 7, 8], cells);
+            assert_eq!(4, a[4]);
+            for b in &SYMMETRIES[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn start_undoes_the_history() {
+        let mut game = Board::from_notation("X../.O./... x").unwrap();
+        game.add_turn(8);
+        game.add_turn(2);
+        assert_eq!(&[8, 2], game.history());
+        assert_eq!("X../.O./... x", game.start().to_notation());
+    }
+
+    #[test]
+    fn history_keeps_legal_moves_only() {
+        let mut game = Board::new();
+        game.add_turn(4);
+        game.add_turn(4);
+        game.add_turn(0);
+        assert_eq!(&[4, 0], game.history());
+    }
 }
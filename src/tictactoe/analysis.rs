@@ -0,0 +1,287 @@
+/// Full search of the game tree, to evaluate positions and review the moves of a game
+use super::record::cell_name;
+use super::tablebase::Tablebase;
+use super::{Board, Field, Player};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// What a position or move is worth for the player to move, with perfect play on both sides
+/// Wins and losses count the moves (of both players) until the game is decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", content = "moves", rename_all = "lowercase")]
+pub enum Value {
+    Win(u8),
+    Draw,
+    Loss(u8),
+}
+impl Value {
+    /// Higher is better, quicker wins beat slower ones and slower losses beat quicker ones
+    pub fn score(&self) -> i32 {
+        match self {
+            Value::Win(n) => 100 - *n as i32,
+            Value::Draw => 0,
+            Value::Loss(n) => *n as i32 - 100,
+        }
+    }
+    /// The value of a position for the player who moved into it
+    pub(super) fn for_mover(&self) -> Value {
+        match self {
+            Value::Win(n) => Value::Loss(n + 1),
+            Value::Draw => Value::Draw,
+            Value::Loss(n) => Value::Win(n + 1),
+        }
+    }
+    fn same_outcome(&self, other: &Value) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Classification {
+    /// As good as any other move
+    Best,
+    /// Keeps the outcome, but wins slower or loses quicker than it had to
+    Inaccuracy,
+    /// Gives away a win or a draw
+    Blunder,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MoveValue {
+    pub cell: usize,
+    /// The cell in record notation, e.g. "b2"
+    pub name: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MoveReview {
+    pub player: Player,
+    #[serde(flatten)]
+    pub played: MoveValue,
+    /// What the best move would have been worth
+    pub best: Value,
+    pub classification: Classification,
+}
+
+/// Everything there is to know about a position, and how it was reached
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Analysis {
+    pub position: String,
+    pub to_move: Player,
+    /// Worth of the position for the player to move
+    pub value: Value,
+    /// Every legal move, best first
+    pub moves: Vec<MoveValue>,
+    /// The moves that led here from the starting position
+    pub history: Vec<MoveReview>,
+    /// The move that led here, if any
+    pub last_move: Option<MoveReview>,
+}
+
+/// Values of solved positions, keyed on `Board::canonical_hash` so symmetric positions
+/// are solved once
+#[derive(Debug, Default)]
+pub struct TranspositionTable {
+    values: RwLock<HashMap<u32, Value>>,
+}
+impl TranspositionTable {
+    /// The table every solver uses unless told otherwise
+    pub fn shared() -> Arc<TranspositionTable> {
+        static SHARED: OnceLock<Arc<TranspositionTable>> = OnceLock::new();
+        Arc::clone(SHARED.get_or_init(Default::default))
+    }
+    fn get(&self, hash: u32) -> Option<Value> {
+        // a panic while holding the lock cannot leave a wrong value behind
+        self.values.read().unwrap_or_else(|e| e.into_inner()).get(&hash).copied()
+    }
+    fn insert(&self, hash: u32, value: Value) {
+        self.values.write().unwrap_or_else(|e| e.into_inner()).insert(hash, value);
+    }
+    pub fn len(&self) -> usize {
+        self.values.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Searches positions, backed by a transposition table
+#[derive(Debug)]
+pub struct Solver {
+    table: Arc<TranspositionTable>,
+}
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Solver {
+    /// A solver sharing its table with every other solver
+    pub fn new() -> Self {
+        Solver::with_table(TranspositionTable::shared())
+    }
+
+    pub fn with_table(table: Arc<TranspositionTable>) -> Self {
+        Solver { table }
+    }
+
+    /// Worth of a position for the player to move, from the tablebase if it knows the position
+    pub fn solve(&mut self, board: &Board) -> Value {
+        match Tablebase::get().lookup(board) {
+            Some(entry) => entry.value,
+            None => self.search(board),
+        }
+    }
+
+    /// Worth of a position for the player to move, from a full search
+    pub fn search(&mut self, board: &Board) -> Value {
+        let key = board.canonical_hash();
+        if let Some(value) = self.table.get(key) {
+            return value;
+        }
+        let value = match board.get_winner() {
+            // whoever won moved last
+            Some((Field::X | Field::O, _)) => Value::Loss(0),
+            Some((Field::Empty, _)) => Value::Draw,
+            None => (0..9)
+                .filter(|cell| board.fields[*cell] == Field::Empty)
+                .map(|cell| {
+                    let mut next = board.clone();
+                    next.add_turn(cell);
+                    self.search(&next).for_mover()
+                })
+                .max_by_key(Value::score)
+                .unwrap_or(Value::Draw),
+        };
+        self.table.insert(key, value);
+        value
+    }
+
+    /// Every legal move with its worth for the player to move, best first
+    pub fn moves(&mut self, board: &Board) -> Vec<MoveValue> {
+        if board.get_winner().is_some() {
+            return Vec::new();
+        }
+        let mut moves: Vec<MoveValue> = (0..9)
+            .filter(|cell| board.fields[*cell] == Field::Empty)
+            .map(|cell| {
+                let mut next = board.clone();
+                next.add_turn(cell);
+                MoveValue {
+                    cell,
+                    name: cell_name(cell),
+                    value: self.solve(&next).for_mover(),
+                }
+            })
+            .collect();
+        moves.sort_by_key(|m| -m.value.score());
+        moves
+    }
+
+    /// Evaluate a move played in a position
+    pub fn review(&mut self, board: &Board, cell: usize) -> Option<MoveReview> {
+        let moves = self.moves(board);
+        let best = moves.first()?.value;
+        let played = moves.into_iter().find(|m| m.cell == cell)?;
+        let classification = if played.value == best {
+            Classification::Best
+        } else if played.value.same_outcome(&best) {
+            Classification::Inaccuracy
+        } else {
+            Classification::Blunder
+        };
+        Some(MoveReview {
+            player: board.next_turn,
+            played,
+            best,
+            classification,
+        })
+    }
+
+    /// Analyse a position and review every move of its history
+    pub fn analyse(&mut self, board: &Board) -> Analysis {
+        let mut replay = board.start();
+        let mut history = Vec::new();
+        for cell in board.history() {
+            if let Some(review) = self.review(&replay, *cell) {
+                history.push(review);
+            }
+            replay.add_turn(*cell);
+        }
+        Analysis {
+            position: board.to_notation(),
+            to_move: board.next_turn,
+            value: self.solve(board),
+            moves: self.moves(board),
+            last_move: history.last().cloned(),
+            history,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perfect_play_draws() {
+        assert_eq!(Value::Draw, Solver::new().solve(&Board::new()));
+    }
+
+    #[test]
+    fn symmetric_positions_are_solved_once() {
+        let table = Arc::new(TranspositionTable::default());
+        let mut solver = Solver::with_table(Arc::clone(&table));
+        solver.search(&Board::new());
+        // fewer than the 765 essentially different positions, as games end once drawn
+        assert!(table.len() <= 765);
+        let solved = table.len();
+        solver.search(&Board::from_notation("..X/.../... o").unwrap());
+        assert_eq!(solved, table.len());
+    }
+
+    #[test]
+    fn wins_and_losses_are_counted_in_moves() {
+        let mut solver = Solver::new();
+        // X to move completes the top row
+        let board = Board::from_notation("XX./OO./... x").unwrap();
+        let moves = solver.moves(&board);
+        assert_eq!(MoveValue { cell: 2, name: "c1".into(), value: Value::Win(1) }, moves[0]);
+        // anything else lets O win right away
+        assert_eq!(Value::Loss(2), moves.last().unwrap().value);
+        assert_eq!(Value::Loss(0), solver.solve(&Board::from_notation("XXX/OO./... o").unwrap()));
+    }
+
+    #[test]
+    fn moves_are_classified() {
+        let mut solver = Solver::new();
+        let board = Board::from_notation("XX./OO./... x").unwrap();
+        assert_eq!(Classification::Best, solver.review(&board, 2).unwrap().classification);
+        assert_eq!(Classification::Blunder, solver.review(&board, 8).unwrap().classification);
+        // blocking O sets up a fork that wins
+        let board = Board::from_notation("X.O/.O./..X x").unwrap();
+        assert!(solver.review(&board, 4).is_none());
+        let review = solver.review(&board, 6).unwrap();
+        assert_eq!(Value::Win(3), review.played.value);
+        assert_eq!(Classification::Best, review.classification);
+    }
+
+    #[test]
+    fn history_is_reviewed() {
+        let mut board = Board::new();
+        for cell in [4, 1] {
+            board.add_turn(cell);
+        }
+        let analysis = Solver::new().analyse(&board);
+        assert_eq!(2, analysis.history.len());
+        assert_eq!(Classification::Best, analysis.history[0].classification);
+        // an edge answer to the center loses
+        let last = analysis.last_move.unwrap();
+        assert_eq!(Player::O, last.player);
+        assert_eq!(Classification::Blunder, last.classification);
+        assert!(matches!(analysis.value, Value::Win(_)));
+    }
+}
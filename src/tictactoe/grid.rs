@@ -0,0 +1,152 @@
+/// Boards of any size where some number of pieces in a row wins, the classic game is 3x3 with 3
+use super::mcts::Position;
+use super::{Field, Player};
+
+/// Largest width or height we play on
+pub const MAX_SIZE: usize = 19;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    /// Pieces in a row needed to win
+    in_a_row: usize,
+    fields: Vec<Field>,
+    next_turn: Player,
+    history: Vec<usize>,
+    /// Set once the game is decided, None inside for a draw
+    outcome: Option<Option<Player>>,
+}
+impl Grid {
+    /// An empty grid, None if nobody could ever get enough pieces in a row on it
+    pub fn new(width: usize, height: usize, in_a_row: usize) -> Option<Grid> {
+        if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+            return None;
+        }
+        if in_a_row == 0 || in_a_row > width.max(height) {
+            return None;
+        }
+        Some(Grid {
+            width,
+            height,
+            in_a_row,
+            fields: vec![Field::Empty; width * height],
+            next_turn: Player::X,
+            history: Vec::new(),
+            outcome: None,
+        })
+    }
+
+    /// The cells played so far, in order
+    pub fn history(&self) -> &[usize] {
+        &self.history
+    }
+
+    pub fn get(&self, cell: usize) -> Option<Field> {
+        self.fields.get(cell).copied()
+    }
+
+    /// Play a cell for the side to move, false if the move is not allowed
+    pub fn add_turn(&mut self, cell: usize) -> bool {
+        if self.outcome.is_some() || self.fields.get(cell) != Some(&Field::Empty) {
+            return false;
+        }
+        let piece = match self.next_turn {
+            Player::X => Field::X,
+            Player::O => Field::O,
+        };
+        self.fields[cell] = piece;
+        self.history.push(cell);
+        if self.completes_line(cell, piece) {
+            self.outcome = Some(Some(self.next_turn));
+        } else if self.history.len() == self.fields.len() {
+            self.outcome = Some(None);
+        }
+        self.next_turn = !self.next_turn;
+        true
+    }
+
+    /// Whether the piece on `cell` is part of a long enough line
+    fn completes_line(&self, cell: usize, piece: Field) -> bool {
+        let (x, y) = ((cell % self.width) as isize, (cell / self.width) as isize);
+        [(1, 0), (0, 1), (1, 1), (1, -1)].iter().any(|(dx, dy)| {
+            let count = |sign: isize| {
+                (1..)
+                    .map(|i| (x + sign * i * dx, y + sign * i * dy))
+                    .take_while(|(cx, cy)| {
+                        (0..self.width as isize).contains(cx)
+                            && (0..self.height as isize).contains(cy)
+                            && self.fields[*cy as usize * self.width + *cx as usize] == piece
+                    })
+                    .count()
+            };
+            1 + count(1) + count(-1) >= self.in_a_row
+        })
+    }
+}
+
+impl Position for Grid {
+    fn legal_moves(&self) -> Vec<usize> {
+        if self.outcome.is_some() {
+            return Vec::new();
+        }
+        (0..self.fields.len())
+            .filter(|cell| self.fields[*cell] == Field::Empty)
+            .collect()
+    }
+    fn play(&mut self, cell: usize) {
+        self.add_turn(cell);
+    }
+    fn to_move(&self) -> Player {
+        self.next_turn
+    }
+    fn outcome(&self) -> Option<Option<Player>> {
+        self.outcome
+    }
+    fn moves_played(&self) -> usize {
+        self.history.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_win_in_every_direction() {
+        // a column, a row, both diagonals on a 5x4 board with 3 in a row
+        for cells in [[0, 5, 10], [6, 7, 8], [2, 8, 14], [4, 8, 12]] {
+            let mut grid = Grid::new(5, 4, 3).unwrap();
+            let others = [19, 18, 17];
+            for i in 0..3 {
+                assert!(grid.add_turn(cells[i]));
+                if i < 2 {
+                    assert!(grid.add_turn(others[i]));
+                }
+            }
+            assert_eq!(Some(Some(Player::X)), grid.outcome(), "{cells:?}");
+            assert!(grid.legal_moves().is_empty());
+        }
+    }
+
+    #[test]
+    fn lines_do_not_wrap_around_the_edge() {
+        let mut grid = Grid::new(3, 3, 3).unwrap();
+        for cell in [1, 0, 2, 4, 3] {
+            assert!(grid.add_turn(cell));
+        }
+        assert_eq!(None, grid.outcome());
+    }
+
+    #[test]
+    fn full_grids_are_drawn() {
+        let mut grid = Grid::new(3, 3, 3).unwrap();
+        for cell in [0, 1, 2, 4, 3, 5, 7, 6, 8] {
+            assert_eq!(None, grid.outcome());
+            assert!(grid.add_turn(cell));
+        }
+        assert_eq!(Some(None), grid.outcome());
+        assert!(Grid::new(2, 2, 4).is_none());
+        assert!(Grid::new(MAX_SIZE + 1, 3, 3).is_none());
+    }
+}
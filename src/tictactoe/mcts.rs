@@ -0,0 +1,215 @@
+/// Monte Carlo tree search, for boards too large to search exhaustively
+use super::{Board, Field, Player};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+/// What the search needs to know about a game, implemented by every board variant
+pub trait Position: Clone {
+    /// Cells that may be played next, empty once the game is over
+    fn legal_moves(&self) -> Vec<usize>;
+    /// Play a legal move for the side to move
+    fn play(&mut self, cell: usize);
+    fn to_move(&self) -> Player;
+    /// None while undecided, Some(None) for a draw and Some(Some(winner)) otherwise
+    fn outcome(&self) -> Option<Option<Player>>;
+    /// How many moves were played to reach the position
+    fn moves_played(&self) -> usize;
+}
+
+impl Position for Board {
+    fn legal_moves(&self) -> Vec<usize> {
+        if self.get_winner().is_some() {
+            return Vec::new();
+        }
+        (0..9).filter(|cell| self.fields[*cell] == Field::Empty).collect()
+    }
+    fn play(&mut self, cell: usize) {
+        self.add_turn(cell);
+    }
+    fn to_move(&self) -> Player {
+        self.next_turn
+    }
+    fn outcome(&self) -> Option<Option<Player>> {
+        self.get_winner().map(|(field, _)| Player::try_from(field).ok())
+    }
+    fn moves_played(&self) -> usize {
+        self.fields.iter().filter(|f| **f != Field::Empty).count()
+    }
+}
+
+/// How long and how adventurously the search runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct MctsConfig {
+    /// Playouts per move
+    pub iterations: usize,
+    /// Stop early once this much time is spent, which makes the search depend on the machine
+    pub time: Option<Duration>,
+    /// Same seed, same position, same iterations: same move
+    pub seed: u64,
+    /// How much unexplored moves are favoured over good ones
+    pub exploration: f64,
+}
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            iterations: 2000,
+            time: None,
+            seed: 0,
+            exploration: std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    cell: Option<usize>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<usize>,
+    /// Who made the move leading here
+    mover: Player,
+    visits: u32,
+    /// Wins of the mover in the playouts through this node, draws count half
+    score: f64,
+}
+
+/// Pick a move by running random playouts, favouring moves that did well so far
+pub fn search<P: Position>(position: &P, config: &MctsConfig) -> Option<usize> {
+    let mut moves = position.legal_moves();
+    if moves.len() < 2 {
+        return moves.pop();
+    }
+    // seeding with the move count keeps successive moves of a game from repeating each other
+    let mut rng = StdRng::seed_from_u64(config.seed ^ position.moves_played() as u64);
+    let started = Instant::now();
+    let mut nodes = vec![Node {
+        cell: None,
+        parent: None,
+        children: Vec::new(),
+        untried: moves,
+        mover: !position.to_move(),
+        visits: 0,
+        score: 0.0,
+    }];
+    for _ in 0..config.iterations {
+        if config.time.is_some_and(|t| started.elapsed() >= t) {
+            break;
+        }
+        let mut current = 0;
+        let mut state = position.clone();
+        // selection
+        while nodes[current].untried.is_empty() && !nodes[current].children.is_empty() {
+            let parent_visits = (nodes[current].visits as f64).ln();
+            current = *nodes[current]
+                .children
+                .iter()
+                .max_by(|a, b| {
+                    uct(&nodes[**a], parent_visits, config.exploration)
+                        .total_cmp(&uct(&nodes[**b], parent_visits, config.exploration))
+                })
+                .unwrap();
+            state.play(nodes[current].cell.unwrap());
+        }
+        // expansion
+        if !nodes[current].untried.is_empty() {
+            let i = rng.gen_range(0..nodes[current].untried.len());
+            let cell = nodes[current].untried.swap_remove(i);
+            let mover = state.to_move();
+            state.play(cell);
+            nodes.push(Node {
+                cell: Some(cell),
+                parent: Some(current),
+                children: Vec::new(),
+                untried: state.legal_moves(),
+                mover,
+                visits: 0,
+                score: 0.0,
+            });
+            let child = nodes.len() - 1;
+            nodes[current].children.push(child);
+            current = child;
+        }
+        // playout
+        while let Some(cell) = state.legal_moves().choose(&mut rng) {
+            state.play(*cell);
+        }
+        let outcome = state.outcome().flatten();
+        // backpropagation
+        let mut node = Some(current);
+        while let Some(i) = node {
+            nodes[i].visits += 1;
+            nodes[i].score += match outcome {
+                Some(winner) if winner == nodes[i].mover => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+            node = nodes[i].parent;
+        }
+    }
+    // the most visited move is the most trusted, ties go to the lower cell
+    nodes[0]
+        .children
+        .iter()
+        .map(|c| &nodes[*c])
+        .max_by(|a, b| a.visits.cmp(&b.visits).then(b.cell.cmp(&a.cell)))
+        .and_then(|n| n.cell)
+        .or_else(|| position.legal_moves().first().copied())
+}
+
+fn uct(node: &Node, parent_visits_ln: f64, exploration: f64) -> f64 {
+    let visits = node.visits as f64;
+    node.score / visits + exploration * (parent_visits_ln / visits).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tictactoe::grid::Grid;
+
+    #[test]
+    fn takes_the_win_and_blocks() {
+        let config = MctsConfig::default();
+        let board = Board::from_notation("XX./OO./... x").unwrap();
+        assert_eq!(Some(2), search(&board, &config));
+        let board = Board::from_notation("XX./O../... o").unwrap();
+        assert_eq!(Some(2), search(&board, &config));
+    }
+
+    #[test]
+    fn works_on_large_boards() {
+        let config = MctsConfig { iterations: 3000, ..Default::default() };
+        let mut grid = Grid::new(7, 7, 4).unwrap();
+        // X has three in a row on the top edge, open at one end only
+        for cell in [0, 14, 1, 15, 2, 22] {
+            grid.play(cell);
+        }
+        assert_eq!(Some(3), search(&grid, &config));
+    }
+
+    #[test]
+    fn seeded_searches_repeat() {
+        let config = MctsConfig { iterations: 300, seed: 42, ..Default::default() };
+        let play = || {
+            let mut grid = Grid::new(4, 4, 3).unwrap();
+            while let Some(cell) = search(&grid, &config) {
+                grid.play(cell);
+            }
+            grid.history().to_vec()
+        };
+        assert_eq!(play(), play());
+    }
+
+    #[test]
+    fn time_budget_stops_the_search() {
+        let config = MctsConfig {
+            iterations: usize::MAX,
+            time: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        assert!(search(&Grid::new(9, 9, 5).unwrap(), &config).is_some());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,286 @@
+/// A portable text notation for whole games, modelled after chess PGN
+///
+/// ```text
+/// [Event "Tic-Tac-Toe"]
+/// [Date "2026.10.19"]
+/// [X "alice"]
+/// [O "bob"]
+/// [Variant "Standard"]
+/// [Result "1-0"]
+///
+/// 1. a1 b2 2. b1 c3 3. c1 1-0
+/// ```
+///
+/// Cells are named by column a to c from left to right and row 1 to 3 from top to bottom,
+/// results are "1-0" if X won, "0-1" if O won, "1/2-1/2" for a draw and "*" while undecided.
+/// Games that were set up from a position carry it in a Position header, see `Board::to_notation`.
+use super::{Board, Field, NotationError};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+    BadHeader(String),
+    BadCell(String),
+    IllegalMove(String),
+    BadPosition(NotationError),
+    /// The result header or the result at the end of the moves does not match the game
+    WrongResult(String),
+}
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadHeader(h) => write!(f, "Malformed header: {h}"),
+            Self::BadCell(c) => write!(f, "Not a cell: {c}"),
+            Self::IllegalMove(c) => write!(f, "Illegal move: {c}"),
+            Self::BadPosition(e) => write!(f, "Bad position: {e}"),
+            Self::WrongResult(r) => write!(f, "Result does not match the moves: {r}"),
+        }
+    }
+}
+impl std::error::Error for RecordError {}
+
+/// Name of a cell, 0 is "a1" and 8 is "c3"
+pub fn cell_name(cell: usize) -> String {
+    let column = (b'a' + (cell % 3) as u8) as char;
+    format!("{column}{}", cell / 3 + 1)
+}
+
+/// Index of a named cell
+pub fn parse_cell(name: &str) -> Result<usize, RecordError> {
+    let mut chars = name.chars();
+    let column = match chars.next() {
+        Some(c @ 'a'..='c') => c as usize - 'a' as usize,
+        _ => return Err(RecordError::BadCell(name.to_string())),
+    };
+    let row = match (chars.next(), chars.next()) {
+        (Some(r @ '1'..='3'), None) => r as usize - '1' as usize,
+        _ => return Err(RecordError::BadCell(name.to_string())),
+    };
+    Ok(row * 3 + column)
+}
+
+/// A date in the "YYYY.MM.DD" form of the Date header
+pub fn date(time: SystemTime) -> String {
+    let days = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86400).unwrap_or(0) as i64;
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+/// The result token of a board
+pub fn result_of(board: &Board) -> &'static str {
+    match board.get_winner() {
+        Some((Field::X, _)) => "1-0",
+        Some((Field::O, _)) => "0-1",
+        Some((Field::Empty, _)) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+/// A whole game: headers in the order they were given, and the cells played
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Record {
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<usize>,
+}
+impl Record {
+    /// Record of a board, results are taken from the board and need not be among the headers
+    pub fn new(headers: Vec<(String, String)>, board: &Board) -> Self {
+        let mut headers: Vec<(String, String)> = headers
+            .into_iter()
+            .filter(|(k, _)| k != "Result" && k != "Position")
+            .collect();
+        let start = board.start().to_notation();
+        if start != Board::new().to_notation() {
+            headers.push(("Position".into(), start));
+        }
+        headers.push(("Result".into(), result_of(board).into()));
+        Record {
+            headers,
+            moves: board.history().to_vec(),
+        }
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every position of the game, from the starting position to the final one
+    pub fn boards(&self) -> Result<Vec<Board>, RecordError> {
+        let mut board = match self.header("Position") {
+            Some(position) => Board::from_notation(position).map_err(RecordError::BadPosition)?,
+            None => Board::new(),
+        };
+        let mut boards = vec![board.clone()];
+        for cell in &self.moves {
+            if !board.add_turn(*cell) {
+                return Err(RecordError::IllegalMove(cell_name(*cell)));
+            }
+            boards.push(board.clone());
+        }
+        Ok(boards)
+    }
+}
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in &self.headers {
+            writeln!(f, "[{key} \"{}\"]", value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+        for (i, cell) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                write!(f, "{}. ", i / 2 + 1)?;
+            }
+            write!(f, "{} ", cell_name(*cell))?;
+        }
+        writeln!(f, "{}", self.header("Result").unwrap_or("*"))
+    }
+}
+impl FromStr for Record {
+    type Err = RecordError;
+    /// Parses a record and checks that its moves are legal and its result matches them
+    fn from_str(s: &str) -> Result<Self, RecordError> {
+        let mut record = Record::default();
+        let mut result = None;
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(header) = line.strip_prefix('[') {
+                record.headers.push(parse_header(header)?);
+                continue;
+            }
+            for token in line.split_whitespace() {
+                if token.ends_with('.') && token[..token.len() - 1].parse::<usize>().is_ok() {
+                    continue;
+                }
+                if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token) {
+                    result = Some(token.to_string());
+                    continue;
+                }
+                record.moves.push(parse_cell(token)?);
+            }
+        }
+        let board = record.boards()?.pop().unwrap_or_default();
+        let actual = result_of(&board);
+        for given in [result.as_deref(), record.header("Result")].into_iter().flatten() {
+            if given != actual {
+                return Err(RecordError::WrongResult(given.to_string()));
+            }
+        }
+        if record.header("Result").is_none() {
+            record.headers.push(("Result".into(), actual.into()));
+        }
+        Ok(record)
+    }
+}
+
+/// Parses `Key "Value"]`, the opening bracket is already gone
+fn parse_header(header: &str) -> Result<(String, String), RecordError> {
+    let bad = || RecordError::BadHeader(header.to_string());
+    let header = header.strip_suffix(']').ok_or_else(bad)?;
+    let (key, value) = header.split_once(' ').ok_or_else(bad)?;
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(bad)?;
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(bad());
+    }
+    Ok((key.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn played(moves: &[usize]) -> Board {
+        let mut board = Board::new();
+        for cell in moves {
+            assert!(board.add_turn(*cell));
+        }
+        board
+    }
+
+    #[test]
+    fn cells_are_named_by_column_and_row() {
+        assert_eq!("a1", cell_name(0));
+        assert_eq!("c1", cell_name(2));
+        assert_eq!("b2", cell_name(4));
+        assert_eq!("c3", cell_name(8));
+        for cell in 0..9 {
+            assert_eq!(Ok(cell), parse_cell(&cell_name(cell)));
+        }
+        assert!(parse_cell("d1").is_err());
+        assert!(parse_cell("a4").is_err());
+        assert!(parse_cell("a12").is_err());
+    }
+
+    #[test]
+    fn dates_are_formatted_like_pgn() {
+        assert_eq!("1970.01.01", date(UNIX_EPOCH));
+        let leap_day = UNIX_EPOCH + std::time::Duration::from_secs(951_782_400);
+        assert_eq!("2000.02.29", date(leap_day));
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let board = played(&[0, 4, 1, 8, 2]);
+        let record = Record::new(
+            vec![("X".into(), "alice \"the great\"".into()), ("O".into(), "bob".into())],
+            &board,
+        );
+        let text = record.to_string();
+        assert!(text.ends_with("1. a1 b2 2. b1 c3 3. c1 1-0\n"));
+        let parsed: Record = text.parse().unwrap();
+        assert_eq!(record, parsed);
+        assert_eq!(Some("alice \"the great\""), parsed.header("X"));
+        let boards = parsed.boards().unwrap();
+        assert_eq!(6, boards.len());
+        assert_eq!(board.show(), boards[5].show());
+    }
+
+    #[test]
+    fn set_up_games_keep_their_position() {
+        let mut board = Board::from_notation("X../.O./... x").unwrap();
+        board.add_turn(8);
+        let record = Record::new(Vec::new(), &board);
+        assert_eq!(Some("X../.O./... x"), record.header("Position"));
+        let parsed: Record = record.to_string().parse().unwrap();
+        assert_eq!(board.show(), parsed.boards().unwrap()[1].show());
+        assert!(matches!(
+            "[Position \"XXX/.../... o\"]".parse::<Record>(),
+            Err(RecordError::BadPosition(_))
+        ));
+    }
+
+    #[test]
+    fn unfinished_games_have_no_result() {
+        let record = Record::new(Vec::new(), &played(&[4]));
+        assert_eq!(Some("*"), record.header("Result"));
+        assert_eq!(record, record.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn bad_records_are_refused() {
+        assert!(matches!("1. a1 a1".parse::<Record>(), Err(RecordError::IllegalMove(_))));
+        assert!(matches!("1. a1 e5".parse::<Record>(), Err(RecordError::BadCell(_))));
+        assert!(matches!("[X alice]".parse::<Record>(), Err(RecordError::BadHeader(_))));
+        assert!(matches!("1. a1 b1 1-0".parse::<Record>(), Err(RecordError::WrongResult(_))));
+        assert!(matches!(
+            "[Result \"0-1\"]\n1. a1 b2 2. b1 c3 3. c1".parse::<Record>(),
+            Err(RecordError::WrongResult(_))
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+/// Every legal position solved up front, built once on first use
+use super::analysis::Value;
+use super::{Board, Field};
+use std::sync::OnceLock;
+
+/// Number of ways to fill nine cells with X, O or nothing
+const CODES: usize = 19683;
+
+/// What the tablebase knows about a position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry {
+    /// Worth of the position for the player to move
+    pub value: Value,
+    /// Bit n is set if playing cell n keeps that value
+    best: u16,
+}
+impl Entry {
+    /// Every move that keeps the value of the position, none if the game is over
+    pub fn best_moves(&self) -> Vec<usize> {
+        (0..9).filter(|cell| self.best & (1 << cell) != 0).collect()
+    }
+}
+
+/// Solved positions, indexed by their fields in base 3
+#[derive(Debug)]
+pub struct Tablebase {
+    entries: Vec<Option<Entry>>,
+}
+impl Tablebase {
+    /// The tablebase of the running program, built the first time it is asked for
+    pub fn get() -> &'static Tablebase {
+        static TABLEBASE: OnceLock<Tablebase> = OnceLock::new();
+        TABLEBASE.get_or_init(Tablebase::build)
+    }
+
+    fn build() -> Tablebase {
+        let mut entries = vec![None; CODES];
+        for code in 0..CODES {
+            if let Ok(board) = Board::from_fields(decode(code as u32), None) {
+                solve_into(&mut entries, &board);
+            }
+        }
+        Tablebase { entries }
+    }
+
+    /// The entry of a position, None for positions that cannot come up in a game
+    pub fn lookup(&self, board: &Board) -> Option<&Entry> {
+        self.entries
+            .get(Board::encode(&board.fields) as usize)?
+            .as_ref()
+    }
+
+    /// How many positions are stored
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The fields encoded by `Board::encode`
+fn decode(mut code: u32) -> [Field; 9] {
+    let mut fields = [Field::Empty; 9];
+    for field in fields.iter_mut().rev() {
+        *field = match code % 3 {
+            1 => Field::X,
+            2 => Field::O,
+            _ => Field::Empty,
+        };
+        code /= 3;
+    }
+    fields
+}
+
+fn solve_into(entries: &mut [Option<Entry>], board: &Board) -> Value {
+    let code = Board::encode(&board.fields) as usize;
+    if let Some(entry) = entries[code] {
+        return entry.value;
+    }
+    let entry = match board.get_winner() {
+        // whoever won moved last
+        Some((Field::X | Field::O, _)) => Entry { value: Value::Loss(0), best: 0 },
+        Some((Field::Empty, _)) => Entry { value: Value::Draw, best: 0 },
+        None => {
+            let moves: Vec<(usize, Value)> = (0..9)
+                .filter(|cell| board.fields[*cell] == Field::Empty)
+                .map(|cell| {
+                    let mut next = board.clone();
+                    next.add_turn(cell);
+                    (cell, solve_into(entries, &next).for_mover())
+                })
+                .collect();
+            let value = moves
+                .iter()
+                .map(|(_, v)| *v)
+                .max_by_key(Value::score)
+                .unwrap_or(Value::Draw);
+            let best = moves
+                .iter()
+                .filter(|(_, v)| *v == value)
+                .fold(0, |best, (cell, _)| best | 1 << cell);
+            Entry { value, best }
+        }
+    };
+    entries[code] = Some(entry);
+    entry.value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tictactoe::analysis::{Solver, TranspositionTable};
+    use std::sync::Arc;
+
+    #[test]
+    fn every_legal_position_is_stored() {
+        // the well known count of positions that can come up in a game
+        assert_eq!(5478, Tablebase::get().len());
+        // every opening move draws
+        let empty = Tablebase::get().lookup(&Board::new()).unwrap();
+        assert_eq!(Value::Draw, empty.value);
+        assert_eq!(9, empty.best_moves().len());
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        for code in [0, 1, 3, 4242, CODES as u32 - 1] {
+            assert_eq!(code, Board::encode(&decode(code)));
+        }
+    }
+
+    #[test]
+    fn tablebase_agrees_with_the_solver() {
+        let mut solver = Solver::with_table(Arc::new(TranspositionTable::default()));
+        let tablebase = Tablebase::get();
+        for code in 0..CODES as u32 {
+            let board = match Board::from_fields(decode(code), None) {
+                Ok(b) => b,
+                Err(_) => {
+                    assert!(tablebase.entries[code as usize].is_none());
+                    continue;
+                }
+            };
+            let entry = tablebase.lookup(&board).unwrap();
+            assert_eq!(solver.search(&board), entry.value, "{}", board.to_notation());
+            let best: Vec<usize> = (0..9)
+                .filter(|cell| board.get_winner().is_none() && board.fields[*cell] == Field::Empty)
+                .filter(|cell| {
+                    let mut next = board.clone();
+                    next.add_turn(*cell);
+                    solver.search(&next).for_mover() == entry.value
+                })
+                .collect();
+            assert_eq!(entry.best_moves(), best, "{}", board.to_notation());
+        }
+    }
+}
@@ -0,0 +1,536 @@
+/// Types and logic of tournaments, independent of the games they are played in
+use crate::profile::sanitize_name;
+use serde::{Deserialize, Serialize};
+
+pub const MIN_ENTRANTS: usize = 2;
+pub const MAX_ENTRANTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TournamentError {
+    InvalidName,
+    AlreadyStarted,
+    TooManyEntrants,
+    NotEnoughEntrants,
+    NotFound,
+    NotOrganizer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// Losers drop out, draws are replayed
+    SingleElimination,
+    /// Everyone plays everyone once, a win is worth a point and a draw half of one
+    RoundRobin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Registering,
+    Running,
+    Finished,
+}
+
+#[derive(Debug, Clone)]
+struct Entrant {
+    name: String,
+    /// lets the entrant take their seat in their games
+    key: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Winner(usize),
+    Draw,
+    Bye,
+}
+
+#[derive(Debug, Clone)]
+struct Pairing {
+    x: usize,
+    /// None for a bye
+    o: Option<usize>,
+    gameid: Option<String>,
+    outcome: Option<Outcome>,
+}
+
+/// A pairing that needs a game, identified by round and position in the round
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewPairing {
+    pub round: usize,
+    pub index: usize,
+    /// Seat keys of the entrant playing X and the entrant playing O
+    pub keys: [String; 2],
+}
+
+#[derive(Debug, Clone)]
+pub struct Tournament {
+    name: String,
+    format: Format,
+    entrants: Vec<Entrant>,
+    rounds: Vec<Vec<Pairing>>,
+    status: Status,
+}
+impl Tournament {
+    pub fn new(name: &str, format: Format) -> Result<Self, TournamentError> {
+        Ok(Tournament {
+            name: sanitize_name(name).ok_or(TournamentError::InvalidName)?,
+            format,
+            entrants: Vec::new(),
+            rounds: Vec::new(),
+            status: Status::Registering,
+        })
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Add an entrant, returns the key they use to take their seats
+    pub fn register(&mut self, name: &str) -> Result<String, TournamentError> {
+        if self.status != Status::Registering {
+            return Err(TournamentError::AlreadyStarted);
+        }
+        if self.entrants.len() >= MAX_ENTRANTS {
+            return Err(TournamentError::TooManyEntrants);
+        }
+        let name = sanitize_name(name).ok_or(TournamentError::InvalidName)?;
+        let key = nanoid::nanoid!(12);
+        self.entrants.push(Entrant {
+            name,
+            key: key.clone(),
+        });
+        Ok(key)
+    }
+
+    /// Close registration and pair the entrants, the returned pairings need games
+    pub fn start(&mut self) -> Result<Vec<NewPairing>, TournamentError> {
+        if self.status != Status::Registering {
+            return Err(TournamentError::AlreadyStarted);
+        }
+        if self.entrants.len() < MIN_ENTRANTS {
+            return Err(TournamentError::NotEnoughEntrants);
+        }
+        self.status = Status::Running;
+        match self.format {
+            Format::SingleElimination => {
+                let everyone: Vec<usize> = (0..self.entrants.len()).collect();
+                self.push_round(&everyone);
+            }
+            Format::RoundRobin => {
+                for round in round_robin(self.entrants.len()) {
+                    self.rounds.push(
+                        round
+                            .into_iter()
+                            .map(|(x, o)| Pairing {
+                                x,
+                                o,
+                                gameid: None,
+                                outcome: if o.is_none() { Some(Outcome::Bye) } else { None },
+                            })
+                            .collect(),
+                    );
+                }
+            }
+        }
+        Ok(self.advance())
+    }
+
+    /// Remember which game a pairing is played in
+    pub fn assign_game(&mut self, round: usize, index: usize, gameid: String) {
+        if let Some(pairing) = self.rounds.get_mut(round).and_then(|r| r.get_mut(index)) {
+            pairing.gameid = Some(gameid);
+        }
+    }
+
+    /// Record the result of a game, `keys` are the seat keys of Player X and Player O,
+    /// `winner` is None for a draw. Returns pairings of a new round if one starts.
+    /// Results for games that are not part of the tournament, or already decided, are ignored.
+    pub fn record(
+        &mut self,
+        gameid: &str,
+        keys: [Option<&str>; 2],
+        winner: Option<usize>,
+    ) -> Vec<NewPairing> {
+        let pairing = self
+            .rounds
+            .iter_mut()
+            .flatten()
+            .find(|p| p.gameid.as_deref() == Some(gameid) && p.outcome.is_none());
+        let pairing = match pairing {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        let outcome = match winner {
+            None => Outcome::Draw,
+            Some(seat) => {
+                let key = keys[seat];
+                let entrant = [Some(pairing.x), pairing.o]
+                    .into_iter()
+                    .flatten()
+                    .find(|e| Some(self.entrants[*e].key.as_str()) == key);
+                match entrant {
+                    Some(e) => Outcome::Winner(e),
+                    None => return Vec::new(),
+                }
+            }
+        };
+        // a knockout needs a winner, drawn games are simply played again
+        if outcome == Outcome::Draw && self.format == Format::SingleElimination {
+            return Vec::new();
+        }
+        pairing.outcome = Some(outcome);
+        self.advance()
+    }
+
+    /// Decide a game its entrants did not join in time: an entrant who took their seat wins,
+    /// if neither did the entrant playing X goes through. `seated` are the keys of those who did,
+    /// the pairings are returned as from `record`
+    pub fn no_show(&mut self, gameid: &str, seated: &[String]) -> Vec<NewPairing> {
+        let pairing = self
+            .rounds
+            .iter_mut()
+            .flatten()
+            .find(|p| p.gameid.as_deref() == Some(gameid) && p.outcome.is_none());
+        let pairing = match pairing {
+            Some(p) => p,
+            None => return Vec::new(),
+        };
+        let showed_up = [Some(pairing.x), pairing.o]
+            .into_iter()
+            .flatten()
+            .find(|e| seated.contains(&self.entrants[*e].key));
+        pairing.outcome = Some(Outcome::Winner(showed_up.unwrap_or(pairing.x)));
+        self.advance()
+    }
+
+    /// Pairings still waiting for a game, and a new knockout round once the last one is decided
+    fn advance(&mut self) -> Vec<NewPairing> {
+        if self.format == Format::SingleElimination {
+            let last = self.rounds.last().cloned().unwrap_or_default();
+            if !last.is_empty() && last.iter().all(|p| p.outcome.is_some()) {
+                let winners: Vec<usize> = last
+                    .iter()
+                    .filter_map(|p| match p.outcome {
+                        Some(Outcome::Winner(e)) => Some(e),
+                        Some(Outcome::Bye) => Some(p.x),
+                        _ => None,
+                    })
+                    .collect();
+                if winners.len() > 1 {
+                    self.push_round(&winners);
+                    return self.advance();
+                }
+            }
+        }
+        if self.rounds.iter().flatten().all(|p| p.outcome.is_some()) {
+            self.status = Status::Finished;
+        }
+        let mut new = Vec::new();
+        for (round, pairings) in self.rounds.iter().enumerate() {
+            for (index, p) in pairings.iter().enumerate() {
+                if let (Some(o), None, None) = (p.o, &p.gameid, p.outcome) {
+                    new.push(NewPairing {
+                        round,
+                        index,
+                        keys: [self.entrants[p.x].key.clone(), self.entrants[o].key.clone()],
+                    });
+                }
+            }
+        }
+        new
+    }
+
+    /// Pair entrants in order, the last one gets a bye if their number is odd
+    fn push_round(&mut self, entrants: &[usize]) {
+        let round = entrants
+            .chunks(2)
+            .map(|pair| Pairing {
+                x: pair[0],
+                o: pair.get(1).copied(),
+                gameid: None,
+                outcome: if pair.len() == 1 { Some(Outcome::Bye) } else { None },
+            })
+            .collect();
+        self.rounds.push(round);
+    }
+
+    fn winner(&self) -> Option<usize> {
+        if self.status != Status::Finished {
+            return None;
+        }
+        match self.format {
+            Format::SingleElimination => match self.rounds.last()?.first()?.outcome? {
+                Outcome::Winner(e) => Some(e),
+                Outcome::Bye => Some(self.rounds.last()?.first()?.x),
+                Outcome::Draw => None,
+            },
+            Format::RoundRobin => self.table().first().map(|(e, _)| *e),
+        }
+    }
+
+    /// Entrants with their records, best first
+    fn table(&self) -> Vec<(usize, Record)> {
+        let mut table: Vec<(usize, Record)> =
+            (0..self.entrants.len()).map(|e| (e, Record::default())).collect();
+        for p in self.rounds.iter().flatten() {
+            match (p.outcome, p.o) {
+                (Some(Outcome::Winner(w)), Some(o)) => {
+                    let loser = if w == p.x { o } else { p.x };
+                    table[w].1.wins += 1;
+                    table[loser].1.losses += 1;
+                }
+                (Some(Outcome::Draw), Some(o)) => {
+                    table[p.x].1.draws += 1;
+                    table[o].1.draws += 1;
+                }
+                _ => (),
+            }
+        }
+        table.sort_by(|(_, a), (_, b)| b.points().total_cmp(&a.points()));
+        table
+    }
+
+    /// What gets published about the tournament
+    pub fn standings(&self) -> Standings {
+        let name = |e: usize| self.entrants[e].name.clone();
+        Standings {
+            name: self.name.clone(),
+            format: self.format,
+            status: self.status,
+            winner: self.winner().map(name),
+            table: self
+                .table()
+                .into_iter()
+                .map(|(e, record)| Standing {
+                    name: name(e),
+                    points: record.points(),
+                    record,
+                })
+                .collect(),
+            rounds: self
+                .rounds
+                .iter()
+                .map(|round| {
+                    round
+                        .iter()
+                        .map(|p| PairingInfo {
+                            x: name(p.x),
+                            o: p.o.map(name),
+                            url: p.gameid.as_ref().map(|id| format!("/{id}/game")),
+                            gameid: p.gameid.clone(),
+                            result: p.outcome.map(|o| match o {
+                                Outcome::Winner(e) => name(e),
+                                Outcome::Draw => "draw".into(),
+                                Outcome::Bye => "bye".into(),
+                            }),
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The rounds of a round robin by the circle method, None stands for a bye
+fn round_robin(entrants: usize) -> Vec<Vec<(usize, Option<usize>)>> {
+    let mut circle: Vec<Option<usize>> = (0..entrants).map(Some).collect();
+    if entrants % 2 == 1 {
+        circle.push(None);
+    }
+    let n = circle.len();
+    let mut rounds = Vec::new();
+    for round in 0..n - 1 {
+        let mut pairings = Vec::new();
+        for i in 0..n / 2 {
+            // alternate colours so nobody always plays X
+            let (a, b) = if round % 2 == 0 {
+                (circle[i], circle[n - 1 - i])
+            } else {
+                (circle[n - 1 - i], circle[i])
+            };
+            match (a, b) {
+                (Some(a), b) => pairings.push((a, b)),
+                (None, Some(b)) => pairings.push((b, None)),
+                (None, None) => (),
+            }
+        }
+        rounds.push(pairings);
+        // keep the first entrant in place, rotate the rest
+        let last = circle.pop().unwrap();
+        circle.insert(1, last);
+    }
+    rounds
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+impl Record {
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    pub name: String,
+    pub points: f64,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PairingInfo {
+    pub x: String,
+    pub o: Option<String>,
+    pub gameid: Option<String>,
+    pub url: Option<String>,
+    /// The winner's name, "draw" or "bye", None while undecided
+    pub result: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standings {
+    pub name: String,
+    pub format: Format,
+    pub status: Status,
+    pub winner: Option<String>,
+    pub table: Vec<Standing>,
+    pub rounds: Vec<Vec<PairingInfo>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assigns game ids to new pairings the way the game manager would
+    fn play(t: &mut Tournament, new: Vec<NewPairing>) -> Vec<(String, [String; 2])> {
+        new.into_iter()
+            .map(|p| {
+                let gameid = format!("{}-{}", p.round, p.index);
+                t.assign_game(p.round, p.index, gameid.clone());
+                (gameid, p.keys)
+            })
+            .collect()
+    }
+
+    fn seats(keys: &[String; 2]) -> [Option<&str>; 2] {
+        [Some(&keys[0]), Some(&keys[1])]
+    }
+
+    #[test]
+    fn single_elimination_advances_winners() {
+        let mut t = Tournament::new("Office cup", Format::SingleElimination).unwrap();
+        for name in ["a", "b", "c", "d", "e"] {
+            t.register(name).unwrap();
+        }
+        let new = t.start().unwrap();
+        let games = play(&mut t, new);
+        // five entrants: two games and a bye
+        assert_eq!(2, games.len());
+        assert_eq!(Some("bye".to_string()), t.standings().rounds[0][2].result);
+
+        // a draw is replayed
+        assert!(t.record(&games[0].0, seats(&games[0].1), None).is_empty());
+        assert!(t.record(&games[0].0, seats(&games[0].1), Some(0)).is_empty());
+        let next = t.record(&games[1].0, seats(&games[1].1), Some(1));
+        assert_eq!(1, next.len());
+
+        let standings = t.standings();
+        assert_eq!(2, standings.rounds.len());
+        assert_eq!("a", standings.rounds[1][0].x);
+        assert_eq!(Some("d".to_string()), standings.rounds[1][0].o);
+        assert_eq!("e", standings.rounds[1][1].x);
+        assert_eq!(Status::Running, standings.status);
+    }
+
+    #[test]
+    fn single_elimination_crowns_a_winner() {
+        let mut t = Tournament::new("Final", Format::SingleElimination).unwrap();
+        let key_a = t.register("a").unwrap();
+        let key_b = t.register("b").unwrap();
+        let new = t.start().unwrap();
+        let games = play(&mut t, new);
+        // results for other games change nothing
+        assert!(t.record("elsewhere", [Some(&key_b), Some(&key_a)], Some(0)).is_empty());
+        assert_eq!(Status::Running, t.status());
+        // b sits in seat X after a rematch swapped seats
+        assert!(t.record(&games[0].0, [Some(&key_b), Some(&key_a)], Some(0)).is_empty());
+        assert_eq!(Status::Finished, t.status());
+        assert_eq!(Some("b".to_string()), t.standings().winner);
+    }
+
+    #[test]
+    fn no_shows_lose() {
+        let mut t = Tournament::new("Final", Format::SingleElimination).unwrap();
+        t.register("a").unwrap();
+        let key_b = t.register("b").unwrap();
+        let new = t.start().unwrap();
+        let games = play(&mut t, new);
+        let seated = vec![key_b];
+        assert!(t.no_show("elsewhere", &seated).is_empty());
+        assert!(t.no_show(&games[0].0, &seated).is_empty());
+        assert_eq!(Some("b".to_string()), t.standings().winner);
+
+        // nobody showed up, the bracket still moves on
+        let mut t = Tournament::new("Final", Format::SingleElimination).unwrap();
+        t.register("a").unwrap();
+        t.register("b").unwrap();
+        let new = t.start().unwrap();
+        let games = play(&mut t, new);
+        t.no_show(&games[0].0, &[]);
+        assert_eq!(Some("a".to_string()), t.standings().winner);
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_once() {
+        for n in 2..9 {
+            let mut seen = std::collections::HashSet::new();
+            for (a, b) in round_robin(n).into_iter().flatten() {
+                if let Some(b) = b {
+                    assert!(seen.insert((a.min(b), a.max(b))));
+                }
+            }
+            assert_eq!(n * (n - 1) / 2, seen.len());
+        }
+    }
+
+    #[test]
+    fn round_robin_counts_points() {
+        let mut t = Tournament::new("League", Format::RoundRobin).unwrap();
+        for name in ["a", "b", "c"] {
+            t.register(name).unwrap();
+        }
+        let new = t.start().unwrap();
+        let games = play(&mut t, new);
+        assert_eq!(3, games.len());
+        t.record(&games[0].0, seats(&games[0].1), None);
+        // only the first result of a pairing counts
+        t.record(&games[0].0, seats(&games[0].1), Some(0));
+        t.record(&games[1].0, seats(&games[1].1), Some(0));
+        assert_eq!(Status::Running, t.status());
+        t.record(&games[2].0, seats(&games[2].1), Some(1));
+        let standings = t.standings();
+        assert_eq!(Status::Finished, standings.status);
+        let total: f64 = standings.table.iter().map(|s| s.points).sum();
+        assert_eq!(3.0, total);
+        assert_eq!(1, standings.table.iter().map(|s| s.record.draws).max().unwrap());
+        assert_eq!(Some(standings.table[0].name.clone()), standings.winner);
+    }
+
+    #[test]
+    fn registration_closes_on_start() {
+        let mut t = Tournament::new("Cup", Format::RoundRobin).unwrap();
+        t.register("a").unwrap();
+        assert_eq!(Err(TournamentError::NotEnoughEntrants), t.start());
+        t.register("b").unwrap();
+        t.start().unwrap();
+        assert_eq!(Err(TournamentError::AlreadyStarted), t.register("c"));
+        assert!(Tournament::new("  ", Format::RoundRobin).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+/// Plays on a game server from the terminal, without the web client
+///
+/// ```text
+/// tictactoe-cli [--server http://localhost:8080] [--name Ann] [--avatar 🦊] [--passphrase secret] [--bot perfect] [game URL]
+/// ```
+///
+/// Without a game URL a new game is created, against `--bot` if one is given. Arrow keys or hjkl
+/// pick a cell, enter or space plays it, 1 to 9 play a cell directly, r asks for a rematch and q quits.
+use crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Print, Stylize};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use multiplayer_tictactoe::game::GameInfo;
+use multiplayer_tictactoe::tictactoe::Field;
+use std::io::Write;
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+use tictactoe_client::{Client, ClientError, Join, NewGame, ServerMessage};
+use tokio::runtime::Runtime;
+
+const DEFAULT_SERVER: &str = "http://localhost:8080";
+
+#[derive(Default)]
+struct Options {
+    server: Option<String>,
+    game: Option<String>,
+    name: Option<String>,
+    avatar: Option<String>,
+    passphrase: Option<String>,
+    bot: Option<String>,
+}
+
+/// What the terminal gets to show, from the event stream or from its own requests
+#[derive(Debug)]
+enum Update {
+    Message(ServerMessage),
+    /// A request failed, with the reason
+    Failed(String),
+    /// The event stream ended, with the reason
+    Closed(String),
+}
+
+/// Server and game id of a game URL like `http://localhost:8080/abc123/game`, or of a bare game id
+fn parse_game(game: &str, server: &str) -> Result<(String, String), String> {
+    let rest = match game.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some(_) => return Err(format!("Only http servers are supported: {game}")),
+        None => return Ok((server.trim_end_matches('/').into(), game.into())),
+    };
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    match path.split('/').next() {
+        Some(id) if !id.is_empty() => Ok((format!("http://{authority}"), id.into())),
+        _ => Err(format!("No game in {game}")),
+    }
+}
+
+/// Everything the terminal shows
+struct Screen {
+    url: String,
+    info: Option<GameInfo>,
+    credentials: Option<String>,
+    message: String,
+    cursor: usize,
+}
+
+enum Action {
+    Play(usize),
+    Rematch,
+    Quit,
+    Redraw,
+    Nothing,
+}
+
+impl Screen {
+    /// Whether the update changed what is shown
+    fn update(&mut self, update: Update) -> bool {
+        match update {
+            Update::Message(ServerMessage::State(info)) => self.info = Some(info),
+            Update::Message(ServerMessage::Credentials(credentials)) => {
+                self.credentials = Some(credentials)
+            }
+            Update::Message(ServerMessage::Notification(message))
+            | Update::Failed(message)
+            | Update::Closed(message) => self.message = message,
+            Update::Message(_) => return false,
+        }
+        true
+    }
+
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Action {
+        let (row, column) = (self.cursor / 3, self.cursor % 3);
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Action::Quit,
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('r') => return Action::Rematch,
+            KeyCode::Enter | KeyCode::Char(' ') => return Action::Play(self.cursor),
+            KeyCode::Char(c @ '1'..='9') => return Action::Play(c as usize - '1' as usize),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = (row + 2) % 3 * 3 + column,
+            KeyCode::Down | KeyCode::Char('j') => self.cursor = (row + 1) % 3 * 3 + column,
+            KeyCode::Left | KeyCode::Char('h') => self.cursor = row * 3 + (column + 2) % 3,
+            KeyCode::Right | KeyCode::Char('l') => self.cursor = row * 3 + (column + 1) % 3,
+            _ => return Action::Nothing,
+        }
+        Action::Redraw
+    }
+
+    fn draw(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let mut lines = vec![format!("Tic-Tac-Toe  {}", self.url), String::new()];
+        let info = match &self.info {
+            Some(info) => info,
+            None => {
+                lines.push("Waiting for the server...".into());
+                return show(out, &lines, None);
+            }
+        };
+        let player = |seat: usize, mark: &str| match &info.players[seat] {
+            Some(p) => format!(
+                "{mark}  {} {}",
+                p.avatar.as_deref().unwrap_or_default(),
+                p.name
+            ),
+            None => format!("{mark}  waiting..."),
+        };
+        lines.push(format!("{:<30}{}", player(0, "X"), player(1, "O")));
+        lines.push(String::new());
+        lines.push("      a   b   c".into());
+        let board = lines.len();
+        for row in 0..3 {
+            let cells: Vec<String> = (0..3)
+                .map(|c| format!(" {} ", mark(info.gamestate[row * 3 + c])))
+                .collect();
+            lines.push(format!("  {}  {}", row + 1, cells.join("│")));
+            if row < 2 {
+                lines.push("     ───┼───┼───".into());
+            }
+        }
+        lines.push(String::new());
+        lines.push(match info.outcome {
+            Some((Field::Empty, _)) => "It's a draw! Press r for a rematch".into(),
+            Some((winner, _)) => format!("Player {winner} wins! Press r for a rematch"),
+            None => self.message.clone(),
+        });
+        lines.push(String::new());
+        lines.push("arrows or hjkl move, enter plays, 1-9 play a cell, r rematch, q quits".into());
+        let (row, column) = (self.cursor / 3, self.cursor % 3);
+        let highlight = (
+            board + row * 2,
+            5 + column * 4,
+            mark(info.gamestate[self.cursor]),
+        );
+        show(out, &lines, Some(highlight))
+    }
+}
+
+fn mark(field: Field) -> &'static str {
+    match field {
+        Field::X => "X",
+        Field::O => "O",
+        Field::Empty => "·",
+    }
+}
+
+/// Draws the lines from the top of the screen, with the cell under the cursor reversed
+fn show(
+    out: &mut impl Write,
+    lines: &[String],
+    highlight: Option<(usize, usize, &str)>,
+) -> std::io::Result<()> {
+    queue!(out, Clear(ClearType::All))?;
+    for (row, line) in lines.iter().enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16), Print(line))?;
+    }
+    if let Some((row, column, mark)) = highlight {
+        queue!(
+            out,
+            cursor::MoveTo(column as u16, row as u16),
+            Print(format!(" {mark} ").reverse())
+        )?;
+    }
+    out.flush()
+}
+
+/// Raw mode on the alternate screen for as long as it lives, also when the client panics
+struct Terminal;
+impl Terminal {
+    fn enter() -> std::io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Terminal)
+    }
+}
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Runs a request in the background, only failures are shown
+fn send(
+    runtime: &Runtime,
+    request: impl std::future::Future<Output = Result<(), ClientError>> + Send + 'static,
+    updates: &Sender<Update>,
+    failed: &'static str,
+) {
+    let updates = updates.clone();
+    runtime.spawn(async move {
+        if let Err(e) = request.await {
+            let _ = updates.send(Update::Failed(format!("{failed}: {e}")));
+        }
+    });
+}
+
+fn play(runtime: &Runtime, client: &Client, game: &str, options: &Options) -> Result<(), String> {
+    let join = Join {
+        name: options.name.clone(),
+        avatar: options.avatar.clone(),
+        passphrase: options.passphrase.clone(),
+    };
+    let mut events = runtime
+        .block_on(client.join(game, &join))
+        .map_err(|e| e.to_string())?;
+    let (tx, rx) = mpsc::channel();
+    let updates = tx.clone();
+    runtime.spawn(async move {
+        let reason = loop {
+            match events.next().await {
+                Some(Ok(event)) => {
+                    if updates.send(Update::Message(event)).is_err() {
+                        return;
+                    }
+                }
+                Some(Err(e)) => break format!("Lost the connection: {e}"),
+                None => break "The server ended the game".into(),
+            }
+        };
+        let _ = updates.send(Update::Closed(reason));
+    });
+    let mut screen = Screen {
+        url: client.url(game),
+        info: None,
+        credentials: None,
+        message: String::new(),
+        cursor: 4,
+    };
+    let terminal = Terminal::enter().map_err(|e| e.to_string())?;
+    let mut out = std::io::stdout();
+    let mut dirty = true;
+    loop {
+        while let Ok(update) = rx.try_recv() {
+            dirty |= screen.update(update);
+        }
+        if dirty {
+            screen.draw(&mut out).map_err(|e| e.to_string())?;
+            dirty = false;
+        }
+        if !event::poll(Duration::from_millis(50)).map_err(|e| e.to_string())? {
+            continue;
+        }
+        let key = match event::read().map_err(|e| e.to_string())? {
+            event::Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            event::Event::Resize(..) => {
+                dirty = true;
+                continue;
+            }
+            _ => continue,
+        };
+        let (client, game) = (client.clone(), game.to_string());
+        let credentials = screen.credentials.clone().unwrap_or_default();
+        match screen.key(key.code, key.modifiers) {
+            Action::Quit => break,
+            Action::Redraw => dirty = true,
+            Action::Nothing => (),
+            Action::Play(cell) => send(
+                runtime,
+                async move { client.play(&game, cell, &credentials).await },
+                &tx,
+                "That move is not possible",
+            ),
+            Action::Rematch => send(
+                runtime,
+                async move { client.rematch(&game, &credentials).await },
+                &tx,
+                "No rematch yet",
+            ),
+        }
+    }
+    drop(terminal);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--server" => &mut options.server,
+            "--name" => &mut options.name,
+            "--avatar" => &mut options.avatar,
+            "--passphrase" => &mut options.passphrase,
+            "--bot" => &mut options.bot,
+            game if !game.starts_with("--") && options.game.is_none() => {
+                options.game = Some(game.into());
+                continue;
+            }
+            _ => return usage(&format!("Unknown argument {arg}")),
+        };
+        match args.next() {
+            Some(v) => *value = Some(v),
+            None => return usage(&format!("Missing value for {arg}")),
+        }
+    }
+    let runtime = match Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let server = options
+        .server
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SERVER.into());
+    let game = match &options.game {
+        Some(game) => parse_game(game, &server).map(|(server, id)| (Client::new(&server), id)),
+        None => {
+            let client = Client::new(&server);
+            let new_game = NewGame {
+                passphrase: options.passphrase.clone(),
+                bot: options.bot.clone(),
+                ..Default::default()
+            };
+            match runtime.block_on(client.create(&new_game)) {
+                Ok(id) => Ok((client, id)),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+    };
+    match game.and_then(|(client, id)| play(&runtime, &client, &id, &options)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage(error: &str) -> ExitCode {
+    eprintln!(
+        "{error}\nusage: tictactoe-cli [--server URL] [--name name] [--avatar avatar] [--passphrase secret] [--bot name] [game URL]"
+    );
+    ExitCode::from(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_are_found_by_url_or_id() {
+        let server = "http://localhost:8080/";
+        assert_eq!(
+            Ok(("http://example.org:9000".into(), "abc".into())),
+            parse_game("http://example.org:9000/abc/game", server)
+        );
+        assert_eq!(
+            Ok(("http://localhost:8080".into(), "abc".into())),
+            parse_game("abc", server)
+        );
+        assert!(parse_game("https://example.org/abc/game", server).is_err());
+        assert!(parse_game("http://example.org/", server).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+/// Reading the server's event streams into its own messages
+use crate::ClientError;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use multiplayer_tictactoe::protocol::ServerMessage;
+use std::collections::VecDeque;
+
+/// Splits the bytes of a server-sent event stream into the names and data of its events
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// The start of a line that has not ended yet
+    buffer: Vec<u8>,
+    name: String,
+    data: Vec<String>,
+}
+impl Parser {
+    /// Takes the next bytes of the stream, and returns the events they complete
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<(String, String)> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() || !self.name.is_empty() {
+                    events.push((std::mem::take(&mut self.name), self.data.join("\n")));
+                }
+                self.data.clear();
+            } else if let Some(value) = line.strip_prefix("event:") {
+                self.name = value.trim_start().into();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).into());
+            }
+            // anything else is a comment, like the keep-alives
+        }
+        events
+    }
+}
+
+/// The messages of an event stream as they arrive
+pub struct Events {
+    stream: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    parser: Parser,
+    ready: VecDeque<(String, String)>,
+}
+impl Events {
+    pub(crate) fn new(response: reqwest::Response) -> Self {
+        Events {
+            stream: response
+                .bytes_stream()
+                .map(|chunk| chunk.map(|b| b.to_vec()))
+                .boxed(),
+            parser: Parser::default(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// The next message, None once the server ended the stream
+    pub async fn next(&mut self) -> Option<Result<ServerMessage, ClientError>> {
+        loop {
+            if let Some((name, data)) = self.ready.pop_front() {
+                return Some(
+                    ServerMessage::parse(&data)
+                        .map(|m| m.message)
+                        .map_err(|e| ClientError::InvalidEvent(format!("{name}: {e}"))),
+                );
+            }
+            match self.stream.next().await? {
+                Ok(bytes) => self.ready.extend(self.parser.feed(&bytes)),
+                Err(e) => return Some(Err(ClientError::Http(e))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_split_wherever_the_chunks_end() {
+        let stream = "event: credentials\ndata: secret\n\n: ping\n\nevent: notification\r\ndata: Your turn, Ann!\r\n\r\n\
+            event: startgame\ndata\n\nevent: standings\ndata: []\n\n";
+        for size in [1, 2, 7, stream.len()] {
+            let mut parser = Parser::default();
+            let events: Vec<_> = stream
+                .as_bytes()
+                .chunks(size)
+                .flat_map(|c| parser.feed(c))
+                .collect();
+            assert_eq!(
+                vec![
+                    ("credentials".to_string(), "secret".to_string()),
+                    ("notification".into(), "Your turn, Ann!".into()),
+                    ("startgame".into(), "".into()),
+                    ("standings".into(), "[]".into()),
+                ],
+                events
+            );
+        }
+    }
+}
@@ -0,0 +1,253 @@
+/// A client for the game server, for bots and tools that play without the web client
+///
+/// Messages are the server's own, see `multiplayer_tictactoe::protocol`, so the client breaks at
+/// compile time where the server changes what it sends.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), tictactoe_client::ClientError> {
+/// use tictactoe_client::{Client, Join, NewGame, ServerMessage};
+///
+/// let client = Client::new("http://localhost:8080");
+/// let game = client.create(&NewGame { bot: Some("perfect".into()), ..Default::default() }).await?;
+/// let mut events = client.join(&game, &Join::default()).await?;
+/// while let Some(event) = events.next().await {
+///     if let ServerMessage::Credentials(credentials) = event? {
+///         client.play(&game, 4, &credentials).await?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub mod event;
+
+pub use event::Events;
+pub use multiplayer_tictactoe::protocol::{ClientMessage, Join, NewGame, ServerMessage};
+use reqwest::{redirect, Method, StatusCode};
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The server could not be reached or the connection broke
+    Http(reqwest::Error),
+    /// The server refused the request, with its reason
+    Refused(StatusCode, String),
+    NoSuchGame,
+    WrongPassphrase,
+    /// The server sent an event this client cannot read
+    InvalidEvent(String),
+}
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "Could not talk to the server: {e}"),
+            Self::Refused(status, reason) if reason.is_empty() => {
+                write!(f, "The server answered with {status}")
+            }
+            Self::Refused(_, reason) => write!(f, "{reason}"),
+            Self::NoSuchGame => write!(f, "There is no such game"),
+            Self::WrongPassphrase => write!(f, "Could not join, check the passphrase"),
+            Self::InvalidEvent(e) => write!(f, "The server sent an unknown event: {e}"),
+        }
+    }
+}
+impl std::error::Error for ClientError {}
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+/// Talks to one server, cheap to clone
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    server: String,
+}
+impl Client {
+    /// A client of the server at this URL, like `http://localhost:8080`
+    pub fn new(server: &str) -> Self {
+        let http = reqwest::Client::builder()
+            // new games answer with a redirect to the game, which is all we need of them
+            .redirect(redirect::Policy::none())
+            .build()
+            .unwrap_or_default();
+        Client {
+            http,
+            server: server.trim_end_matches('/').into(),
+        }
+    }
+
+    /// Where the web client shows the game
+    pub fn url(&self, game: &str) -> String {
+        format!("{}/{game}/game", self.server)
+    }
+
+    /// Sends the message as the request the server expects it in
+    pub async fn send(
+        &self,
+        game: &str,
+        credentials: &str,
+        message: &ClientMessage,
+    ) -> Result<reqwest::Response, ClientError> {
+        let method = Method::from_bytes(message.method().as_bytes()).unwrap_or(Method::GET);
+        let url = format!("{}{}", self.server, message.path(game, credentials));
+        let request = match message {
+            ClientMessage::NewGame(options) => self.http.request(method, url).query(options),
+            ClientMessage::Join(join) => self.http.request(method, url).query(join),
+            ClientMessage::Chat(text) => self.http.request(method, url).body(text.clone()),
+            _ => self.http.request(method, url),
+        };
+        let response = request.send().await?;
+        match response.status() {
+            StatusCode::FORBIDDEN if matches!(message, ClientMessage::Join(_)) => {
+                Err(ClientError::WrongPassphrase)
+            }
+            _ => check(response).await,
+        }
+    }
+
+    /// Creates a game and returns its id
+    pub async fn create(&self, options: &NewGame) -> Result<String, ClientError> {
+        let response = self
+            .send("", "", &ClientMessage::NewGame(options.clone()))
+            .await?;
+        let location = response
+            .headers()
+            .get("Location")
+            .and_then(|l| l.to_str().ok());
+        match location.and_then(|l| l.split('/').next()) {
+            Some(id) if !id.is_empty() => Ok(id.into()),
+            _ => Err(ClientError::Refused(
+                response.status(),
+                "The server did not create a game".into(),
+            )),
+        }
+    }
+
+    /// Takes a seat, or watches if both are taken, and returns what happens in the game
+    pub async fn join(&self, game: &str, join: &Join) -> Result<Events, ClientError> {
+        let response = self
+            .send(game, "", &ClientMessage::Join(join.clone()))
+            .await?;
+        Ok(Events::new(response))
+    }
+
+    /// Plays the cell, counted from 0 in the top left corner
+    pub async fn play(
+        &self,
+        game: &str,
+        cell: usize,
+        credentials: &str,
+    ) -> Result<(), ClientError> {
+        self.send(game, credentials, &ClientMessage::Move(cell))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn rematch(&self, game: &str, credentials: &str) -> Result<(), ClientError> {
+        self.send(game, credentials, &ClientMessage::Rematch)
+            .await
+            .map(|_| ())
+    }
+}
+
+/// The response if the server did what was asked
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, ClientError> {
+    let status = response.status();
+    if status.is_success() || status.is_redirection() {
+        return Ok(response);
+    }
+    match status {
+        StatusCode::NOT_FOUND => Err(ClientError::NoSuchGame),
+        _ => Err(ClientError::Refused(
+            status,
+            response.text().await.unwrap_or_default(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multiplayer_tictactoe::protocol::Versioned;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A server that answers requests in turn with these responses, and returns the request lines it got
+    async fn stub(responses: Vec<String>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let length = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..length]);
+                requests.push(request.lines().next().unwrap_or_default().to_string());
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn games_are_created_joined_and_played() {
+        let events: String = [
+            ServerMessage::Credentials("secret".into()),
+            ServerMessage::Notification("Game Ready, make the first move!".into()),
+        ]
+        .into_iter()
+        .map(|m| {
+            format!(
+                "event: {}\ndata: {}\n\n",
+                m.event(),
+                serde_json::to_string(&Versioned::new(m)).unwrap()
+            )
+        })
+        .collect();
+        let (url, requests) = stub(vec![
+            "HTTP/1.1 302 Found\r\nLocation: abc/game\r\nConnection: close\r\nContent-Length: 0\r\n\r\n".into(),
+            format!("HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{events}", events.len()),
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0\r\n\r\n".into(),
+            "HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n".into(),
+        ])
+        .await;
+        let client = Client::new(&url);
+        let options = NewGame {
+            bot: Some("perfect".into()),
+            ..Default::default()
+        };
+        let game = client.create(&options).await.unwrap();
+        assert_eq!("abc", game);
+        let join = Join {
+            name: Some("Ann".into()),
+            ..Default::default()
+        };
+        let mut events = client.join(&game, &join).await.unwrap();
+        assert_eq!(
+            ServerMessage::Credentials("secret".into()),
+            events.next().await.unwrap().unwrap()
+        );
+        assert!(matches!(
+            events.next().await,
+            Some(Ok(ServerMessage::Notification(_)))
+        ));
+        assert!(events.next().await.is_none());
+        client.play(&game, 4, "secret").await.unwrap();
+        assert!(matches!(
+            client.rematch(&game, "secret").await,
+            Err(ClientError::Refused(..))
+        ));
+        assert_eq!(
+            vec![
+                "GET /newgame?public=false&rated=false&bot=perfect HTTP/1.1",
+                "GET /abc/events?name=Ann HTTP/1.1",
+                "POST /abc/4/secret HTTP/1.1",
+                "GET /abc/rematch/secret HTTP/1.1",
+            ],
+            requests.await.unwrap()
+        );
+    }
+}